@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use super::misc::Reader;
+use super::primitives::sha256;
+use super::write_optional_fingerprint;
+use super::{EncodedShard, Error, Fingerprint, Header};
+
+/* Archive
+ *
+ * A single file holding the header and the encoded shards of a file:
+ *
+ * magic || version || len (u32) || header || optional key fingerprint || record*
+ *
+ * where every record is
+ *
+ * index (u16) || len (u32) || SHA-256(shard) || shard
+ *
+ * Records are read until the end of the archive (so shards can be appended),
+ * the reader only parses the records and loads the shards on demand.
+ * A shard appended again (e.g. to replace a corrupted copy)
+ * supersedes the earlier records with the same index.
+ */
+const MAGIC: [u8; 8] = *b"PORTAGEA";
+const VERSION: u8 = 1;
+
+const CHECKSUM_SIZE: usize = 32;
+const RECORD_SIZE: usize = 2 + 4 + CHECKSUM_SIZE;
+
+/// Writes an archive, see ArchiveReader
+pub struct ArchiveWriter<W: Write> {
+    inner: W,
+}
+
+/// Reads the shards of an archive on demand
+pub struct ArchiveReader<R: Read + Seek> {
+    inner: R,
+    header: Header,
+    key: Option<Fingerprint>,
+    records: BTreeMap<u16, (u64, usize, [u8; CHECKSUM_SIZE])>, // offset, length, checksum
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Starts an archive for the file described by the header
+    pub fn new(mut inner: W, header: &Header) -> Result<Self, Error> {
+        let key = header.fingerprint().cloned();
+        let header = header.serialize();
+        let mut ser =
+            Vec::with_capacity(MAGIC.len() + 1 + 4 + header.len() + 1 + Fingerprint::SIZE);
+        ser.extend(&MAGIC);
+        ser.push(VERSION);
+        ser.extend(&(header.len() as u32).to_be_bytes());
+        ser.extend(&header[..]);
+        write_optional_fingerprint(&mut ser, &key);
+        inner.write_all(&ser[..])?;
+        Ok(ArchiveWriter { inner })
+    }
+
+    /// Appends an encoded shard
+    pub fn write_shard(&mut self, shard: &EncodedShard) -> Result<(), Error> {
+        let ser = shard.serialize();
+        let mut record = Vec::with_capacity(RECORD_SIZE);
+        record.extend(&shard.idx.to_be_bytes());
+        record.extend(&(ser.len() as u32).to_be_bytes());
+        record.extend(&sha256(&ser[..]));
+        self.inner.write_all(&record[..])?;
+        self.inner.write_all(&ser[..])?;
+        Ok(())
+    }
+
+    /// Flushes the archive and returns the underlying writer
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+// reads exactly n bytes, running past the end is a malformed archive
+fn read_bytes<R: Read>(inner: &mut R, n: usize) -> Result<Vec<u8>, Error> {
+    let mut buf = vec![0u8; n];
+    inner.read_exact(&mut buf[..]).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => Error::Malformed,
+        _ => Error::Io(e),
+    })?;
+    Ok(buf)
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    /// Opens an archive, parsing the header and the record of every shard
+    pub fn open(mut inner: R) -> Result<Self, Error> {
+        let start = read_bytes(&mut inner, MAGIC.len() + 1 + 4)?;
+        let mut rd = Reader::new(&start[..]);
+        if rd.take(MAGIC.len())? != MAGIC || rd.u8()? != VERSION {
+            return Err(Error::Malformed);
+        }
+        let len = rd.u32()? as u64;
+
+        // the length is checked against the archive before allocating
+        let pos = inner.stream_position()?;
+        let end = inner.seek(SeekFrom::End(0))?;
+        if len > end.saturating_sub(pos) {
+            return Err(Error::Malformed);
+        }
+        inner.seek(SeekFrom::Start(pos))?;
+        let header = Header::deserialize(&read_bytes(&mut inner, len as usize)?[..])?;
+
+        let key = match read_bytes(&mut inner, 1)?[0] {
+            0 => None,
+            1 => {
+                let mut fp = [0u8; Fingerprint::SIZE];
+                fp.copy_from_slice(&read_bytes(&mut inner, Fingerprint::SIZE)?[..]);
+                Some(Fingerprint::from_bytes(fp))
+            }
+            _ => return Err(Error::Malformed),
+        };
+
+        // index the records until the end of the archive
+        let mut pos = inner.stream_position()?;
+        let mut records = BTreeMap::new();
+        while pos < end {
+            inner.seek(SeekFrom::Start(pos))?;
+            let record = read_bytes(&mut inner, RECORD_SIZE)?;
+            let mut rd = Reader::new(&record[..]);
+            let idx = rd.u16()?;
+            let len = rd.u32()? as usize;
+            let mut checksum = [0u8; CHECKSUM_SIZE];
+            checksum.copy_from_slice(rd.take(CHECKSUM_SIZE)?);
+
+            pos += RECORD_SIZE as u64;
+            if pos + len as u64 > end {
+                return Err(Error::Malformed);
+            }
+            records.insert(idx, (pos, len, checksum));
+            pos += len as u64;
+        }
+
+        Ok(ArchiveReader {
+            inner,
+            header,
+            key,
+            records,
+        })
+    }
+
+    /// Returns the header of the archived file
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the fingerprint of the key used to encode the shards (if recorded)
+    pub fn fingerprint(&self) -> Option<&Fingerprint> {
+        self.key.as_ref()
+    }
+
+    /// Returns the indices of the shards in the archive
+    pub fn indices(&self) -> Vec<u16> {
+        self.records.keys().cloned().collect()
+    }
+
+    /// Loads the shard with the given index (if present),
+    /// fails with Error::Checksum if the shard has been corrupted
+    pub fn load(&mut self, idx: u16) -> Result<Option<EncodedShard>, Error> {
+        let (offset, len, checksum) = match self.records.get(&idx) {
+            Some(record) => *record,
+            None => return Ok(None),
+        };
+        self.inner.seek(SeekFrom::Start(offset))?;
+        let ser = read_bytes(&mut self.inner, len)?;
+        if sha256(&ser[..]) != checksum {
+            return Err(Error::Checksum);
+        }
+        let shard = EncodedShard::deserialize(&ser[..])?;
+        if shard.idx != idx {
+            return Err(Error::Malformed);
+        }
+        Ok(Some(shard))
+    }
+
+    /// Loads every shard which is not corrupted
+    pub fn shards(&mut self) -> Result<Vec<EncodedShard>, Error> {
+        let mut shards = Vec::with_capacity(self.records.len());
+        for idx in self.indices() {
+            match self.load(idx) {
+                Ok(Some(shard)) => shards.push(shard),
+                Ok(None) | Err(Error::Checksum) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(shards)
+    }
+}
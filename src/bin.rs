@@ -1,4 +1,8 @@
 use portage::*;
+use std::env;
+use std::fs;
+use std::io::BufWriter;
+use std::process;
 use std::thread;
 
 const DATA: usize = 1024 * 1024;
@@ -6,13 +10,105 @@ const BLOCK: usize = 1024;
 const WORK: usize = DATA / BLOCK;
 const WORKERS: usize = 8;
 
-pub fn main() {
+const USAGE: &str = "usage:
+    portage keygen <encoding-key> <decoding-key>
+    portage decoding <encoding-key> <decoding-key>
+    portage pack <encoding-key> <input> <archive> [expansion]
+    portage unpack <decoding-key> <archive> <output>
+    portage bench [encoding-key]";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1)
+}
+
+fn password(confirm: bool) -> Vec<u8> {
+    let pw = rpassword::prompt_password("Password: ")
+        .unwrap_or_else(|e| fail(&format!("failed to read password: {}", e)));
+    if confirm {
+        let again = rpassword::prompt_password("Confirm password: ")
+            .unwrap_or_else(|e| fail(&format!("failed to read password: {}", e)));
+        if pw != again {
+            fail("passwords do not match");
+        }
+    }
+    pw.into_bytes()
+}
+
+fn load(path: &str) -> EncodingKey {
+    EncodingKey::load_encrypted(path, &password(false)[..])
+        .unwrap_or_else(|e| fail(&format!("failed to load {}: {}", path, e)))
+}
+
+fn save_decoding(sk: &EncodingKey, path: &str) {
+    fs::write(path, sk.decoding().serialize())
+        .unwrap_or_else(|e| fail(&format!("failed to write {}: {}", path, e)));
+}
+
+fn keygen(enc: &str, dec: &str) {
+    let pw = password(true);
     let sk = EncodingKey::new();
-    let mut bytes1 = Vec::with_capacity(BLOCK);
-    bytes1.resize(bytes1.capacity(), 0u8);
+    sk.save_encrypted(enc, &pw[..])
+        .unwrap_or_else(|e| fail(&format!("failed to write {}: {}", enc, e)));
+    save_decoding(&sk, dec);
+}
+
+fn pack(enc: &str, input: &str, archive: &str, expansion: &str) {
+    let expansion: usize = expansion
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("invalid expansion: {}", expansion)));
+    let mut sk = load(enc);
+    let data =
+        fs::read(input).unwrap_or_else(|e| fail(&format!("failed to read {}: {}", input, e)));
+    let dimension = data.len().div_ceil(SHARD_SIZE);
+    if dimension.saturating_add(expansion) > MAX_SHARDS {
+        fail(&format!(
+            "too many shards for {}: {} data + {} expansion (at most {})",
+            input, dimension, expansion, MAX_SHARDS
+        ));
+    }
+
+    let (header, shards) = sk
+        .encode_file(&File::new(&data[..]), expansion)
+        .unwrap_or_else(|e| fail(&format!("failed to encode {}: {}", input, e)));
+
+    let out = fs::File::create(archive)
+        .unwrap_or_else(|e| fail(&format!("failed to create {}: {}", archive, e)));
+    let write = || -> Result<(), Error> {
+        let mut w = ArchiveWriter::new(BufWriter::new(out), &header)?;
+        for s in shards.iter() {
+            w.write_shard(s)?;
+        }
+        w.finish()?;
+        Ok(())
+    };
+    write().unwrap_or_else(|e| fail(&format!("failed to write {}: {}", archive, e)));
+}
+
+fn unpack(dec: &str, archive: &str, output: &str) {
+    let mut pk = fs::read(dec)
+        .map_err(Error::from)
+        .and_then(|bytes| DecodingKey::deserialize(&bytes[..]))
+        .unwrap_or_else(|e| fail(&format!("failed to load {}: {}", dec, e)));
+
+    let data = fs::File::open(archive)
+        .map_err(Error::from)
+        .and_then(ArchiveReader::open)
+        .and_then(|mut ar| {
+            let shards = ar.shards()?;
+            pk.decode_file(ar.header(), &shards[..])
+        })
+        .and_then(|file| file.unpack_verified())
+        .unwrap_or_else(|e| fail(&format!("failed to unpack {}: {}", archive, e)));
+
+    fs::write(output, data).unwrap_or_else(|e| fail(&format!("failed to write {}: {}", output, e)));
+}
+
+fn bench(sk: EncodingKey) {
+    let bytes1 = vec![0u8; BLOCK];
 
     let file = File::new(&bytes1[..]);
-    let (_, shards) = file.shards(WORKERS - 1);
+    let (_, shards) = file.shards(WORKERS - 1).unwrap();
 
     let mut enc: Vec<EncodedShard> = shards.into_iter().map(|s| s.pack()).collect();
     let mut handles = vec![];
@@ -32,3 +128,18 @@ pub fn main() {
         h.join().unwrap();
     }
 }
+
+pub fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    match &args[..] {
+        ["keygen", enc, dec] => keygen(enc, dec),
+        ["decoding", enc, dec] => save_decoding(&load(enc), dec),
+        ["pack", enc, input, archive] => pack(enc, input, archive, "0"),
+        ["pack", enc, input, archive, expansion] => pack(enc, input, archive, expansion),
+        ["unpack", dec, archive, output] => unpack(dec, archive, output),
+        ["bench"] | [] => bench(EncodingKey::new()),
+        ["bench", enc] => bench(load(enc)),
+        _ => fail(USAGE),
+    }
+}
@@ -0,0 +1,31 @@
+/* Arithmetic backend
+ *
+ * All bignum arithmetic goes through the BigNum / BigNumContext / MontContext
+ * types exported here. Both backends provide the same interface
+ * (modelled on the subset of OpenSSL's BN API used by the encoding)
+ * and must produce bit-identical results:
+ *
+ * - ossl:   OpenSSL bignums (default)
+ * - native: pure-Rust num-bigint (feature "rust-bignum"),
+ *           whose exponentiation is not constant-time:
+ *           timing may leak the trapdoor exponent, use it for decoding only
+ *
+ * Integers are unsigned, byte conversions are big-endian
+ * and the minimal encoding of zero is the empty string.
+ *
+ * A MontContext caches the Montgomery setup for an odd modulus.
+ * Values in Montgomery form (a R mod m) are backend specific,
+ * only the results of converting back out of the form must agree.
+ */
+
+#[cfg(feature = "rust-bignum")]
+mod native;
+
+#[cfg(not(feature = "rust-bignum"))]
+mod ossl;
+
+#[cfg(feature = "rust-bignum")]
+pub use native::{BigNum, BigNumContext, MontContext};
+
+#[cfg(not(feature = "rust-bignum"))]
+pub use ossl::{BigNum, BigNumContext, MontContext};
@@ -0,0 +1,307 @@
+use std::cmp::Ordering;
+
+use num_bigint::{BigUint, RandBigInt};
+use num_integer::Integer;
+use num_traits::{One, Zero};
+
+use super::super::Error;
+
+// rounds of Miller-Rabin during prime generation
+const MR_ROUNDS: usize = 64;
+
+const SMALL_PRIMES: [u32; 24] = [
+    3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+];
+
+// no state is needed, the context only exists to match the OpenSSL interface
+pub struct BigNumContext;
+
+#[derive(Clone, Debug)]
+pub struct BigNum(BigUint);
+
+// num-bigint does not expose Montgomery arithmetic (modpow uses it internally),
+// so the Montgomery form is the identity (R = 1)
+pub struct MontContext {
+    m: BigUint,
+}
+
+impl BigNumContext {
+    pub fn new() -> Result<Self, Error> {
+        Ok(BigNumContext)
+    }
+}
+
+// clearing the bits from the bottom up zeroes every limb in place
+// before the value is normalized (truncated)
+fn wipe(v: &mut BigUint) {
+    for bit in 0..v.bits() {
+        v.set_bit(bit, false);
+    }
+    debug_assert!(v.is_zero());
+}
+
+impl MontContext {
+    pub fn new(m: &BigNum, _ctx: &mut BigNumContext) -> Result<Self, Error> {
+        if m.0.is_even() || m.0.bits() < 2 {
+            return Err(Error::Arithmetic);
+        }
+        Ok(MontContext { m: m.0.clone() })
+    }
+}
+
+// the modulus may be a secret prime
+impl Drop for MontContext {
+    fn drop(&mut self) {
+        wipe(&mut self.m);
+    }
+}
+
+fn is_probable_prime(n: &BigUint) -> bool {
+    for p in SMALL_PRIMES.iter() {
+        if (n % p).is_zero() {
+            return *n == BigUint::from(*p);
+        }
+    }
+
+    // n - 1 = d * 2^s
+    let one = BigUint::one();
+    let n1 = n - &one;
+    let s = n1.trailing_zeros().unwrap();
+    let d = &n1 >> s;
+
+    let mut rng = rand::thread_rng();
+    let two = BigUint::from(2u32);
+    'witness: for _ in 0..MR_ROUNDS {
+        let a = rng.gen_biguint_range(&two, &n1);
+        let mut x = a.modpow(&d, n);
+        if x == one || x == n1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = &x * &x % n;
+            if x == n1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+impl BigNum {
+    pub fn new() -> Result<Self, Error> {
+        Ok(BigNum(BigUint::zero()))
+    }
+
+    pub fn from_u32(v: u32) -> Result<Self, Error> {
+        Ok(BigNum(BigUint::from(v)))
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(BigNum(BigUint::from_bytes_be(bytes)))
+    }
+
+    pub fn copy_from_slice(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.0 = BigUint::from_bytes_be(bytes);
+        Ok(())
+    }
+
+    // assigns the value of a, reusing the allocation
+    pub fn copy_from(&mut self, a: &Self) -> Result<(), Error> {
+        self.0.clone_from(&a.0);
+        Ok(())
+    }
+
+    // minimal big-endian encoding (as to_vec), reusing the buffer
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.clear();
+        for digit in self.0.iter_u64_digits().rev() {
+            out.extend(&digit.to_be_bytes());
+        }
+        let zeros = out.iter().take_while(|b| **b == 0).count();
+        out.drain(..zeros);
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        // OpenSSL encodes zero as the empty string
+        if self.0.is_zero() {
+            return vec![];
+        }
+        self.0.to_bytes_be()
+    }
+
+    pub fn to_vec_padded(&self, len: usize) -> Result<Vec<u8>, Error> {
+        let bs = self.to_vec();
+        if bs.len() > len {
+            return Err(Error::Arithmetic);
+        }
+        let mut padded = vec![0u8; len - bs.len()];
+        padded.extend(bs);
+        Ok(padded)
+    }
+
+    pub fn num_bytes(&self) -> usize {
+        self.0.bits().div_ceil(8) as usize
+    }
+
+    pub fn ucmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+
+    // wipes the memory
+    pub fn clear(&mut self) {
+        wipe(&mut self.0)
+    }
+
+    // num-bigint has no constant-time arithmetic (see mod_exp_mont)
+    pub fn set_const_time(&mut self) {}
+
+    pub fn add_word(&mut self, w: u32) -> Result<(), Error> {
+        self.0 += w;
+        Ok(())
+    }
+
+    pub fn sub_word(&mut self, w: u32) -> Result<(), Error> {
+        let w = BigUint::from(w);
+        if self.0 < w {
+            return Err(Error::Arithmetic);
+        }
+        self.0 -= w;
+        Ok(())
+    }
+
+    pub fn checked_add(&mut self, a: &Self, b: &Self) -> Result<(), Error> {
+        self.0 = &a.0 + &b.0;
+        Ok(())
+    }
+
+    // fails if the difference is negative
+    pub fn checked_sub(&mut self, a: &Self, b: &Self) -> Result<(), Error> {
+        if a.0 < b.0 {
+            return Err(Error::Arithmetic);
+        }
+        self.0 = &a.0 - &b.0;
+        Ok(())
+    }
+
+    pub fn rshift1(&mut self, a: &Self) -> Result<(), Error> {
+        self.0 = &a.0 >> 1;
+        Ok(())
+    }
+
+    // floor of the square root
+    pub fn sqrt(&mut self, a: &Self, _ctx: &mut BigNumContext) -> Result<(), Error> {
+        self.0 = a.0.sqrt();
+        Ok(())
+    }
+
+    pub fn checked_mul(
+        &mut self,
+        a: &Self,
+        b: &Self,
+        _ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        self.0 = &a.0 * &b.0;
+        Ok(())
+    }
+
+    pub fn nnmod(&mut self, a: &Self, m: &Self, _ctx: &mut BigNumContext) -> Result<(), Error> {
+        if m.0.is_zero() {
+            return Err(Error::Arithmetic);
+        }
+        self.0 = &a.0 % &m.0;
+        Ok(())
+    }
+
+    pub fn mod_sub(
+        &mut self,
+        a: &Self,
+        b: &Self,
+        m: &Self,
+        _ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        if m.0.is_zero() {
+            return Err(Error::Arithmetic);
+        }
+        self.0 = (&a.0 % &m.0 + &m.0 - &b.0 % &m.0) % &m.0;
+        Ok(())
+    }
+
+    // a R mod m (R = 1)
+    pub fn mont_form(
+        &mut self,
+        a: &Self,
+        mont: &MontContext,
+        _ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        self.0 = &a.0 % &mont.m;
+        Ok(())
+    }
+
+    // a b R^-1 mod m (R = 1)
+    pub fn mont_mul(
+        &mut self,
+        a: &Self,
+        b: &Self,
+        mont: &MontContext,
+        _ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        self.0 = &a.0 * &b.0 % &mont.m;
+        Ok(())
+    }
+
+    // a^p mod m,
+    // NOT constant-time: the running time depends on the (secret) exponent
+    pub fn mod_exp_mont(
+        &mut self,
+        a: &Self,
+        p: &Self,
+        mont: &MontContext,
+        _ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        self.0 = a.0.modpow(&p.0, &mont.m);
+        Ok(())
+    }
+
+    pub fn mod_inverse(
+        &mut self,
+        a: &Self,
+        m: &Self,
+        _ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        if m.0.is_zero() {
+            return Err(Error::Arithmetic);
+        }
+        self.0 = (&a.0 % &m.0).modinv(&m.0).ok_or(Error::Arithmetic)?;
+        Ok(())
+    }
+
+    // random prime of exactly the given size, congruent to rem mod add
+    pub fn generate_prime(&mut self, bits: usize, add: u32, rem: u32) -> Result<(), Error> {
+        let bits = bits as u64;
+        if bits < 8 || add == 0 || rem >= add {
+            return Err(Error::Arithmetic);
+        }
+
+        let add = BigUint::from(add);
+        let rem = BigUint::from(rem);
+        let mut rng = rand::thread_rng();
+        loop {
+            // random candidate with the top two bits set
+            let mut c = rng.gen_biguint(bits);
+            c.set_bit(bits - 1, true);
+            c.set_bit(bits - 2, true);
+
+            // move to the residue class
+            c = &c - c.mod_floor(&add) + &rem;
+            if c.bits() != bits || c.is_even() {
+                continue;
+            }
+
+            if is_probable_prime(&c) {
+                self.0 = c;
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,330 @@
+// calls into OpenSSL (the only unsafe code in the crate)
+#![allow(unsafe_code)]
+
+use std::cmp::Ordering;
+
+use foreign_types::ForeignTypeRef;
+use openssl::bn;
+use openssl::error::ErrorStack;
+
+use super::super::Error;
+
+pub struct BigNumContext(bn::BigNumContext);
+
+pub struct BigNum(bn::BigNum);
+
+// Montgomery state for a fixed odd modulus
+pub struct MontContext {
+    ptr: *mut ffi::BN_MONT_CTX,
+    m: BigNum,
+}
+
+// the openssl crate does not wrap BN_MONT_CTX (nor BN_copy),
+// the declarations match the prototypes in openssl/bn.h (1.1 and 3.x)
+#[allow(non_camel_case_types)]
+mod ffi {
+    use openssl_sys::{BIGNUM, BN_CTX};
+    use std::os::raw::c_int;
+
+    pub enum BN_MONT_CTX {}
+
+    extern "C" {
+        pub fn BN_copy(a: *mut BIGNUM, b: *const BIGNUM) -> *mut BIGNUM;
+        pub fn BN_MONT_CTX_new() -> *mut BN_MONT_CTX;
+        pub fn BN_MONT_CTX_free(mont: *mut BN_MONT_CTX);
+        pub fn BN_MONT_CTX_set(mont: *mut BN_MONT_CTX, m: *const BIGNUM, ctx: *mut BN_CTX)
+            -> c_int;
+        pub fn BN_to_montgomery(
+            r: *mut BIGNUM,
+            a: *const BIGNUM,
+            mont: *mut BN_MONT_CTX,
+            ctx: *mut BN_CTX,
+        ) -> c_int;
+        pub fn BN_mod_mul_montgomery(
+            r: *mut BIGNUM,
+            a: *const BIGNUM,
+            b: *const BIGNUM,
+            mont: *mut BN_MONT_CTX,
+            ctx: *mut BN_CTX,
+        ) -> c_int;
+        pub fn BN_mod_exp_mont(
+            r: *mut BIGNUM,
+            a: *const BIGNUM,
+            p: *const BIGNUM,
+            m: *const BIGNUM,
+            ctx: *mut BN_CTX,
+            mont: *mut BN_MONT_CTX,
+        ) -> c_int;
+    }
+}
+
+fn cvt(r: std::os::raw::c_int) -> Result<(), Error> {
+    if r <= 0 {
+        Err(ErrorStack::get().into())
+    } else {
+        Ok(())
+    }
+}
+
+// SAFETY: the MontContext exclusively owns the BN_MONT_CTX (freed only on drop)
+// and OpenSSL state is not tied to the thread which allocated it.
+unsafe impl Send for MontContext {}
+
+// SAFETY: the context is only written by BN_MONT_CTX_set in MontContext::new,
+// afterwards it is only passed to BN_to_montgomery, BN_mod_mul_montgomery
+// and BN_mod_exp_mont, which read it (despite the non-const pointers),
+// so concurrent use through shared references does not race.
+unsafe impl Sync for MontContext {}
+
+impl MontContext {
+    pub fn new(m: &BigNum, ctx: &mut BigNumContext) -> Result<Self, Error> {
+        if !m.0.is_bit_set(0) || m.0.num_bits() < 2 {
+            return Err(Error::Arithmetic);
+        }
+        // SAFETY: no arguments, the result is checked for NULL
+        let ptr = unsafe { ffi::BN_MONT_CTX_new() };
+        if ptr.is_null() {
+            return Err(ErrorStack::get().into());
+        }
+        // from here on the context is freed by Drop, also on failure
+        let mont = MontContext { ptr, m: m.clone() };
+        // SAFETY: mont.ptr is a valid, exclusively owned context,
+        // m and ctx are valid for the duration of the call (borrowed)
+        cvt(unsafe { ffi::BN_MONT_CTX_set(mont.ptr, m.0.as_ptr(), ctx.0.as_ptr()) })?;
+        Ok(mont)
+    }
+}
+
+// BN_MONT_CTX_free wipes the modulus, which may be a secret prime
+impl Drop for MontContext {
+    fn drop(&mut self) {
+        self.m.clear();
+        // SAFETY: ptr was returned by BN_MONT_CTX_new and is freed exactly once
+        unsafe { ffi::BN_MONT_CTX_free(self.ptr) }
+    }
+}
+
+impl BigNumContext {
+    pub fn new() -> Result<Self, Error> {
+        Ok(BigNumContext(bn::BigNumContext::new()?))
+    }
+}
+
+impl BigNum {
+    pub fn new() -> Result<Self, Error> {
+        Ok(BigNum(bn::BigNum::new()?))
+    }
+
+    pub fn from_u32(v: u32) -> Result<Self, Error> {
+        Ok(BigNum(bn::BigNum::from_u32(v)?))
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(BigNum(bn::BigNum::from_slice(bytes)?))
+    }
+
+    // assigns from big-endian bytes, reusing the allocation
+    pub fn copy_from_slice(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        Ok(self.0.copy_from_slice(bytes)?)
+    }
+
+    // assigns the value of a, reusing the allocation
+    pub fn copy_from(&mut self, a: &Self) -> Result<(), Error> {
+        // SAFETY: both bignums are valid, and distinct since self is borrowed mutably
+        if unsafe { ffi::BN_copy(self.0.as_ptr(), a.0.as_ptr()) }.is_null() {
+            return Err(ErrorStack::get().into());
+        }
+        Ok(())
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    // minimal big-endian encoding (as to_vec), reusing the buffer
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.resize(self.num_bytes(), 0);
+        // SAFETY: BN_bn2bin writes BN_num_bytes bytes, which is the length of out
+        let n = unsafe { openssl_sys::BN_bn2bin(self.0.as_ptr(), out.as_mut_ptr()) };
+        debug_assert_eq!(n as usize, out.len());
+    }
+
+    pub fn to_vec_padded(&self, len: usize) -> Result<Vec<u8>, Error> {
+        Ok(self.0.to_vec_padded(len as i32)?)
+    }
+
+    pub fn num_bytes(&self) -> usize {
+        self.0.num_bytes() as usize
+    }
+
+    pub fn ucmp(&self, other: &Self) -> Ordering {
+        self.0.ucmp(&other.0)
+    }
+
+    // wipes the memory (BN_clear)
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    // selects the constant-time code paths (BN_FLG_CONSTTIME) for a secret operand
+    pub fn set_const_time(&mut self) {
+        self.0.set_const_time()
+    }
+
+    pub fn add_word(&mut self, w: u32) -> Result<(), Error> {
+        Ok(self.0.add_word(w)?)
+    }
+
+    pub fn sub_word(&mut self, w: u32) -> Result<(), Error> {
+        Ok(self.0.sub_word(w)?)
+    }
+
+    pub fn checked_add(&mut self, a: &Self, b: &Self) -> Result<(), Error> {
+        Ok(self.0.checked_add(&a.0, &b.0)?)
+    }
+
+    // fails if the difference is negative
+    pub fn checked_sub(&mut self, a: &Self, b: &Self) -> Result<(), Error> {
+        if a.ucmp(b) == Ordering::Less {
+            return Err(Error::Arithmetic);
+        }
+        Ok(self.0.checked_sub(&a.0, &b.0)?)
+    }
+
+    pub fn rshift1(&mut self, a: &Self) -> Result<(), Error> {
+        Ok(self.0.rshift1(&a.0)?)
+    }
+
+    // floor of the square root (Newton's method)
+    pub fn sqrt(&mut self, a: &Self, ctx: &mut BigNumContext) -> Result<(), Error> {
+        let mut x = bn::BigNum::new()?;
+        let mut y = bn::BigNum::new()?;
+        let mut q = bn::BigNum::new()?;
+        if a.0.num_bits() > 0 {
+            // start above the root: 2^ceil(bits / 2)
+            x.set_bit((a.0.num_bits() + 1) / 2)?;
+            loop {
+                // y = (x + a / x) / 2
+                q.checked_div(&a.0, &x, &mut ctx.0)?;
+                y.checked_add(&x, &q)?;
+                q.rshift1(&y)?;
+                if q.ucmp(&x) != Ordering::Less {
+                    break;
+                }
+                std::mem::swap(&mut x, &mut q);
+            }
+        }
+        self.0 = x;
+        Ok(())
+    }
+
+    pub fn checked_mul(
+        &mut self,
+        a: &Self,
+        b: &Self,
+        ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        Ok(self.0.checked_mul(&a.0, &b.0, &mut ctx.0)?)
+    }
+
+    pub fn nnmod(&mut self, a: &Self, m: &Self, ctx: &mut BigNumContext) -> Result<(), Error> {
+        Ok(self.0.nnmod(&a.0, &m.0, &mut ctx.0)?)
+    }
+
+    pub fn mod_sub(
+        &mut self,
+        a: &Self,
+        b: &Self,
+        m: &Self,
+        ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        Ok(self.0.mod_sub(&a.0, &b.0, &m.0, &mut ctx.0)?)
+    }
+
+    // a R mod m
+    pub fn mont_form(
+        &mut self,
+        a: &Self,
+        mont: &MontContext,
+        ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        // SAFETY: the bignums, mont.ptr and ctx are valid for the duration of the call,
+        // the output does not alias the input (borrowed mutably)
+        cvt(unsafe {
+            ffi::BN_to_montgomery(self.0.as_ptr(), a.0.as_ptr(), mont.ptr, ctx.0.as_ptr())
+        })
+    }
+
+    // a b R^-1 mod m (a, b < m)
+    pub fn mont_mul(
+        &mut self,
+        a: &Self,
+        b: &Self,
+        mont: &MontContext,
+        ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        // SAFETY: the bignums, mont.ptr and ctx are valid for the duration of the call,
+        // the output does not alias the inputs (borrowed mutably)
+        cvt(unsafe {
+            ffi::BN_mod_mul_montgomery(
+                self.0.as_ptr(),
+                a.0.as_ptr(),
+                b.0.as_ptr(),
+                mont.ptr,
+                ctx.0.as_ptr(),
+            )
+        })
+    }
+
+    // a^p mod m
+    pub fn mod_exp_mont(
+        &mut self,
+        a: &Self,
+        p: &Self,
+        mont: &MontContext,
+        ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        // SAFETY: the bignums, mont.ptr (set up for mont.m) and ctx are valid
+        // for the duration of the call, the output does not alias the inputs
+        cvt(unsafe {
+            ffi::BN_mod_exp_mont(
+                self.0.as_ptr(),
+                a.0.as_ptr(),
+                p.0.as_ptr(),
+                mont.m.0.as_ptr(),
+                ctx.0.as_ptr(),
+                mont.ptr,
+            )
+        })
+    }
+
+    pub fn mod_inverse(
+        &mut self,
+        a: &Self,
+        m: &Self,
+        ctx: &mut BigNumContext,
+    ) -> Result<(), Error> {
+        Ok(self.0.mod_inverse(&a.0, &m.0, &mut ctx.0)?)
+    }
+
+    // random prime of exactly the given size, congruent to rem mod add
+    pub fn generate_prime(&mut self, bits: usize, add: u32, rem: u32) -> Result<(), Error> {
+        let add = bn::BigNum::from_u32(add)?;
+        let rem = bn::BigNum::from_u32(rem)?;
+        Ok(self
+            .0
+            .generate_prime(bits as i32, false, Some(&add), Some(&rem))?)
+    }
+}
+
+impl Clone for BigNum {
+    fn clone(&self) -> Self {
+        BigNum(self.0.to_owned().unwrap())
+    }
+}
+
+impl std::fmt::Debug for BigNum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -0,0 +1,196 @@
+use std::convert::TryFrom;
+
+use super::misc::Reader;
+use super::{Compression, Error, File, FileKey, Params};
+
+/* Bundle
+ *
+ * Packs many (small) files into the contents of a single File,
+ * avoiding the padding and header of every file:
+ *
+ * magic || len (u32) || index || data
+ *
+ * where the index is: count (u32) || entry*
+ * and every entry is: len (u16) || name (UTF-8) || offset (u64) || length (u64)
+ * with offsets relative to the start of the data.
+ *
+ * The index is at the front, so a single file is extracted by reading
+ * the index and the range of the file (see File::read_range).
+ */
+const MAGIC: [u8; 8] = *b"PORTAGEB";
+const PREFIX_SIZE: usize = MAGIC.len() + 4;
+
+/// Entry in the index of a bundle
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BundleEntry {
+    pub name: String,
+    pub offset: u64, // relative to the data
+    pub length: u64,
+}
+
+/// Collects files to be split as a single File
+#[derive(Clone, Debug, Default)]
+pub struct Bundle {
+    entries: Vec<BundleEntry>,
+    data: Vec<u8>,
+}
+
+impl Bundle {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a file, names must be unique and at most 65535 bytes
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        if name.len() > u16::MAX as usize || self.entries.iter().any(|e| e.name == name) {
+            return Err(Error::Malformed);
+        }
+        self.entries.push(BundleEntry {
+            name: name.to_owned(),
+            offset: self.data.len() as u64,
+            length: data.len() as u64,
+        });
+        self.data.extend_from_slice(data);
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[BundleEntry] {
+        &self.entries
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut index: Vec<u8> = Vec::new();
+        index.extend(&(self.entries.len() as u32).to_be_bytes());
+        for e in self.entries.iter() {
+            index.extend(&(e.name.len() as u16).to_be_bytes());
+            index.extend(e.name.as_bytes());
+            index.extend(&e.offset.to_be_bytes());
+            index.extend(&e.length.to_be_bytes());
+        }
+
+        let mut ser = Vec::with_capacity(PREFIX_SIZE + index.len() + self.data.len());
+        ser.extend(&MAGIC);
+        ser.extend(&(index.len() as u32).to_be_bytes());
+        ser.extend(index);
+        ser.extend(&self.data[..]);
+        ser
+    }
+
+    /// Splits the bundle as a single file
+    pub fn file(&self, params: &Params) -> File {
+        File::with_params(&self.serialize()[..], params)
+    }
+}
+
+fn parse_index(bytes: &[u8]) -> Result<Vec<BundleEntry>, Error> {
+    let mut rd = Reader::new(bytes);
+    let count = rd.u32()? as usize;
+    let mut entries: Vec<BundleEntry> = Vec::with_capacity(count.min(bytes.len()));
+    for _ in 0..count {
+        let len = rd.u16()? as usize;
+        let name = String::from_utf8(rd.take(len)?.to_vec()).map_err(|_| Error::Malformed)?;
+        let offset = rd.u64()?;
+        let length = rd.u64()?;
+        if entries.iter().any(|e| e.name == name) {
+            return Err(Error::Malformed);
+        }
+        entries.push(BundleEntry {
+            name,
+            offset,
+            length,
+        });
+    }
+    rd.finish()?;
+    Ok(entries)
+}
+
+// contents of a bundled file: ranges of a segmented file are read on demand,
+// any other file is unpacked (decrypted, decompressed) once
+enum Contents<'a> {
+    Segments(&'a File),
+    Unpacked(Vec<u8>),
+}
+
+impl Contents<'_> {
+    // bytes [offset, offset + len), truncated at the end
+    fn range(&self, offset: usize, len: usize) -> Result<Vec<u8>, Error> {
+        match self {
+            Contents::Segments(file) => file.read_range(offset, len),
+            Contents::Unpacked(data) => {
+                let end = offset.saturating_add(len).min(data.len());
+                Ok(data[offset.min(end)..end].to_vec())
+            }
+        }
+    }
+
+    // index and offset of the data of a bundle
+    fn index(&self) -> Result<(Vec<BundleEntry>, usize), Error> {
+        let prefix = self.range(0, PREFIX_SIZE)?;
+        let mut rd = Reader::new(&prefix[..]);
+        if rd.take(MAGIC.len())? != MAGIC {
+            return Err(Error::Malformed);
+        }
+        let len = rd.u32()? as usize;
+        let index = self.range(PREFIX_SIZE, len)?;
+        if index.len() != len {
+            return Err(Error::Malformed);
+        }
+        Ok((parse_index(&index[..])?, PREFIX_SIZE + len))
+    }
+
+    // contents of a file in the bundle
+    fn extract(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let (entries, start) = self.index()?;
+        let entry = entries
+            .iter()
+            .find(|e| e.name == name)
+            .ok_or(Error::NotFound)?;
+
+        let offset = (start as u64)
+            .checked_add(entry.offset)
+            .ok_or(Error::Malformed)?;
+        let offset = usize::try_from(offset).map_err(|_| Error::Malformed)?;
+        let length = usize::try_from(entry.length).map_err(|_| Error::Malformed)?;
+        let data = self.range(offset, length)?;
+        if data.len() != length {
+            return Err(Error::Malformed);
+        }
+        Ok(data)
+    }
+}
+
+impl File {
+    fn bundle_contents(&self, key: Option<&FileKey>) -> Result<Contents<'_>, Error> {
+        Ok(match (&self.seal, key) {
+            (Some(_), Some(key)) => Contents::Unpacked(self.unpack_with_key(key)?),
+            (Some(_), None) => return Err(Error::Encrypted),
+            (None, _) if self.compression == Compression::None && self.segment != 0 => {
+                Contents::Segments(self)
+            }
+            (None, _) => Contents::Unpacked(self.unpack()?),
+        })
+    }
+
+    /// Lists the files of a bundle (see Bundle),
+    /// fails with Error::Encrypted if the file is encrypted
+    pub fn bundle_entries(&self) -> Result<Vec<BundleEntry>, Error> {
+        Ok(self.bundle_contents(None)?.index()?.0)
+    }
+
+    /// Lists the files of an encrypted bundle (see File::unpack_with_key)
+    pub fn bundle_entries_with_key(&self, key: &FileKey) -> Result<Vec<BundleEntry>, Error> {
+        Ok(self.bundle_contents(Some(key))?.index()?.0)
+    }
+
+    /// Extracts a single file from a bundle (see Bundle),
+    /// decoding only the segments covering the index and the file (if segmented and not compressed).
+    /// Fails with Error::NotFound if the bundle does not contain the file.
+    pub fn bundle_extract(&self, name: &str) -> Result<Vec<u8>, Error> {
+        self.bundle_contents(None)?.extract(name)
+    }
+
+    /// Extracts a single file from an encrypted bundle (see File::unpack_with_key)
+    pub fn bundle_extract_with_key(&self, name: &str, key: &FileKey) -> Result<Vec<u8>, Error> {
+        self.bundle_contents(Some(key))?.extract(name)
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+use super::Error;
+
+/// Compression applied to the input before splitting
+/// (the encoded replicas are incompressible),
+/// part of the parameter set recorded in the header
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+}
+
+impl Compression {
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Result<Self, Error> {
+        match id {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    pub(crate) fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => data.to_owned(),
+            Compression::Deflate => {
+                let mut enc = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                enc.write_all(data).unwrap();
+                enc.finish().unwrap()
+            }
+        }
+    }
+
+    // fails unless the data decompresses to exactly length bytes
+    pub(crate) fn decompress(self, data: &[u8], length: usize) -> Result<Vec<u8>, Error> {
+        let out = match self {
+            Compression::None => data.to_owned(),
+            Compression::Deflate => {
+                // bounded by the recorded length,
+                // which is not trusted for the allocation
+                let mut out = Vec::new();
+                DeflateDecoder::new(data)
+                    .take((length as u64).saturating_add(1))
+                    .read_to_end(&mut out)
+                    .map_err(|_| Error::Malformed)?;
+                out
+            }
+        };
+        if out.len() != length {
+            return Err(Error::Malformed);
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+
+use zeroize::Zeroizing;
+
+use super::misc::Reader;
+use super::primitives::{aes_256_gcm_open, aes_256_gcm_seal, rand_bytes};
+use super::Error;
+
+/* Confidentiality layer
+ *
+ * Optionally the input is encrypted under a user key using AES-256-GCM
+ * before padding and full-domain hashing.
+ * The (random) nonce and the tag are recorded in the header,
+ * the encoding of the ciphertext is otherwise unchanged.
+ */
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+
+// associated data (domain separation)
+const AAD: &[u8] = b"portage-file";
+
+/// Key for encrypting files before splitting, see Params::key
+#[derive(Clone)]
+pub struct FileKey(Zeroizing<[u8; KEY_SIZE]>);
+
+// nonce and tag of an encrypted file
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Seal {
+    nonce: [u8; NONCE_SIZE],
+    tag: [u8; TAG_SIZE],
+}
+
+impl FileKey {
+    pub const SIZE: usize = KEY_SIZE;
+
+    /// Generates a random key
+    pub fn generate() -> Result<Self, Error> {
+        let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+        rand_bytes(&mut key[..])?;
+        Ok(FileKey(key))
+    }
+
+    pub fn from_bytes(bytes: [u8; KEY_SIZE]) -> Self {
+        FileKey(Zeroizing::new(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; KEY_SIZE] {
+        &self.0
+    }
+
+    pub(crate) fn seal(&self, data: &[u8]) -> Result<(Vec<u8>, Seal), Error> {
+        let mut seal = Seal {
+            nonce: [0u8; NONCE_SIZE],
+            tag: [0u8; TAG_SIZE],
+        };
+        rand_bytes(&mut seal.nonce)?;
+        let ct = aes_256_gcm_seal(&self.0[..], &seal.nonce, AAD, data, &mut seal.tag)?;
+        Ok((ct, seal))
+    }
+
+    pub(crate) fn open(&self, data: &[u8], seal: &Seal) -> Result<Vec<u8>, Error> {
+        aes_256_gcm_open(&self.0[..], &seal.nonce, AAD, data, &seal.tag)
+    }
+}
+
+impl fmt::Debug for FileKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FileKey(..)")
+    }
+}
+
+impl Seal {
+    pub(crate) const SIZE: usize = NONCE_SIZE + TAG_SIZE;
+
+    pub(crate) fn write_optional(ser: &mut Vec<u8>, seal: &Option<Seal>) {
+        match seal {
+            Some(seal) => {
+                ser.push(1);
+                ser.extend(&seal.nonce);
+                ser.extend(&seal.tag);
+            }
+            None => ser.push(0),
+        }
+    }
+
+    pub(crate) fn read_optional(rd: &mut Reader) -> Result<Option<Seal>, Error> {
+        match rd.u8()? {
+            0 => Ok(None),
+            1 => {
+                let mut seal = Seal {
+                    nonce: [0u8; NONCE_SIZE],
+                    tag: [0u8; TAG_SIZE],
+                };
+                seal.nonce.copy_from_slice(rd.take(NONCE_SIZE)?);
+                seal.tag.copy_from_slice(rd.take(TAG_SIZE)?);
+                Ok(Some(seal))
+            }
+            _ => Err(Error::Malformed),
+        }
+    }
+}
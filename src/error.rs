@@ -0,0 +1,87 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+#[cfg(feature = "openssl")]
+use openssl::error::ErrorStack;
+
+// non-exhaustive: the variants depend on the enabled features (Crypto)
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Serialized object could not be parsed
+    Malformed,
+    /// Insufficient or inconsistent shards for reconstruction
+    Reconstruct,
+    /// Authenticated decryption failed (wrong password / key or tampered data)
+    Decrypt,
+    /// File is encrypted and can only be recovered using its key (File::unpack_with_key)
+    Encrypted,
+    /// Recovered contents do not match the digest recorded in the header
+    Digest,
+    /// No entry with the given name
+    NotFound,
+    /// Archived shard does not match its checksum
+    Checksum,
+    /// Decoded shard does not fit (forged, corrupted or encoded under a different key)
+    OutOfRange,
+    /// Shard or header was encoded under a different key
+    KeyMismatch,
+    /// No decoding key available for the shard
+    UnknownKey,
+    /// More shards than the code supports (see MAX_SHARDS)
+    TooManyShards,
+    /// Arithmetic failure (e.g. no modular inverse)
+    Arithmetic,
+    /// Failure inside OpenSSL
+    #[cfg(feature = "openssl")]
+    Crypto(ErrorStack),
+    /// Failure reading or writing a file
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Malformed => write!(f, "malformed serialization"),
+            Error::Reconstruct => write!(f, "unable to reconstruct from shards"),
+            Error::Decrypt => write!(f, "decryption failed (wrong password or key?)"),
+            Error::Encrypted => write!(f, "file is encrypted (key required)"),
+            Error::Digest => write!(f, "contents do not match the recorded digest"),
+            Error::NotFound => write!(f, "no such entry"),
+            Error::Checksum => write!(f, "shard does not match its checksum"),
+            Error::OutOfRange => write!(f, "decoded shard out of range"),
+            Error::KeyMismatch => write!(f, "encoded under a different key"),
+            Error::UnknownKey => write!(f, "no decoding key for shard"),
+            Error::TooManyShards => write!(f, "too many shards"),
+            Error::Arithmetic => write!(f, "arithmetic failure"),
+            #[cfg(feature = "openssl")]
+            Error::Crypto(e) => write!(f, "openssl: {}", e),
+            Error::Io(e) => write!(f, "io: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "openssl")]
+            Error::Crypto(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "openssl")]
+impl From<ErrorStack> for Error {
+    fn from(e: ErrorStack) -> Self {
+        Error::Crypto(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -1,23 +1,31 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom};
 use std::mem;
 
-use super::misc::expand;
+use super::primitives::sha256;
 use super::File;
+use super::{Compression, Error, FileKey, Header, Params, PrfKind};
 
 const FDH_ROUNDS: usize = 3;
 
 use super::Shard;
-use super::SHARD_SIZE;
+use super::{BLOCK_SIZE, MAX_SHARDS};
 
 /// Full Domain Hashing
-fn fdh(data: Vec<u8>, rounds: usize, reverse: bool) -> Vec<u8> {
+fn fdh(prf: PrfKind, data: Vec<u8>, rounds: usize, reverse: bool, twk: &[u8]) -> Vec<u8> {
     // split into left/right
     let mut left = data;
     let mut right = left.split_off(left.len() / 2);
 
+    // round number || tweak
+    let mut tweak = Vec::with_capacity(1 + twk.len());
+    tweak.push(0);
+    tweak.extend_from_slice(twk);
+
     // apply feistel
     for r in 0..rounds {
-        let tweak: [u8; 1] = [if reverse { rounds - r - 1 } else { r } as u8];
-        let pad = expand(&tweak, &left[..], right.len());
+        tweak[0] = if reverse { rounds - r - 1 } else { r } as u8;
+        let pad = prf.expand(&tweak, &left[..], right.len());
         for i in 0..right.len() {
             right[i] ^= pad[i];
         }
@@ -31,52 +39,373 @@ fn fdh(data: Vec<u8>, rounds: usize, reverse: bool) -> Vec<u8> {
     left
 }
 
+/* All-or-nothing transform
+ *
+ * Applied to the whole (padded) file,
+ * or independently to every segment of `segment` shards,
+ * in which case the segment index is part of the tweak.
+ * `first` is the index of the first segment in data,
+ * `size` is the size of a segment in bytes.
+ */
+fn aont(prf: PrfKind, size: usize, first: usize, data: Vec<u8>, reverse: bool) -> Vec<u8> {
+    if size == 0 {
+        return fdh(prf, data, FDH_ROUNDS, reverse, &[]);
+    }
+
+    let mut out = Vec::with_capacity(data.len());
+    for (i, seg) in data.chunks(size).enumerate() {
+        let idx = (first + i) as u32;
+        out.extend(fdh(
+            prf,
+            seg.to_vec(),
+            FDH_ROUNDS,
+            reverse,
+            &idx.to_le_bytes(),
+        ));
+    }
+    out
+}
+
 impl File {
     pub fn new(data: &[u8]) -> File {
+        Self::with_params(data, &Params::default())
+    }
+
+    /// Splits the data into shards using the given parameters,
+    /// panics if the shard size is not a positive multiple of BLOCK_SIZE
+    pub fn with_params(data: &[u8], params: &Params) -> File {
+        let shard_size = params.shard_size;
+        assert!(shard_size > 0 && shard_size.is_multiple_of(BLOCK_SIZE));
+        assert!(shard_size <= u32::MAX as usize);
+
+        // digest (omitted if encrypted: it would allow confirming guesses of the contents)
+
+        let digest = match params.key {
+            Some(_) => None,
+            None => Some(sha256(data)),
+        };
+
+        // compress
+
+        let original = data.len();
+        let data = params.compression.compress(data);
+
+        // encrypt
+
+        let (mut data, seal) = match &params.key {
+            Some(key) => {
+                let (ct, seal) = key.seal(&data[..]).unwrap();
+                (ct, Some(seal))
+            }
+            None => (data, None),
+        };
+
         // save length
 
         let length = data.len();
-        let mut data = data.to_owned();
 
         // pad to multiple of shard size
 
-        while data.len() % SHARD_SIZE != 0 {
-            data.push(0x0);
-        }
-        assert_eq!(data.len() % SHARD_SIZE, 0);
+        data.resize(length.next_multiple_of(shard_size), 0x0);
+        assert_eq!(data.len() % shard_size, 0);
 
         // full-domain hashing
 
-        let data = fdh(data, FDH_ROUNDS, false);
-        assert_eq!(data.len() % SHARD_SIZE, 0);
+        let segment = params.segment as usize;
+        let data = aont(params.prf, segment * shard_size, 0, data, false);
+        assert_eq!(data.len() % shard_size, 0);
 
         // split into fixed-sized shards
 
-        let num_shards = data.len() / SHARD_SIZE;
-        let mut shards = Vec::with_capacity(num_shards);
-        assert!(num_shards < 1 << 16);
+        let num_shards = data.len() / shard_size;
+        assert!(num_shards <= MAX_SHARDS);
 
-        for idx in 0..num_shards {
-            let l = idx * SHARD_SIZE;
-            let r = l + SHARD_SIZE;
-            shards.push(Shard::new(idx as u16, &data[l..r]));
-        }
+        let shards = data
+            .chunks_exact(shard_size)
+            .enumerate()
+            .map(|(idx, bytes)| Shard::new(idx as u16, params.prf, bytes))
+            .collect();
 
-        File { length, shards }
+        File {
+            length,
+            prf: params.prf,
+            shard_size,
+            segment,
+            seal,
+            compression: params.compression,
+            original,
+            digest,
+            shards,
+        }
     }
 
-    pub fn unpack(&self) -> Vec<u8> {
+    // the stored bytes: after compression / encryption
+    fn stored(&self) -> Vec<u8> {
         // join all states
 
-        let mut data = Vec::with_capacity(self.shards.len() * SHARD_SIZE);
+        let mut data = Vec::with_capacity(self.shards.len() * self.shard_size);
         for st in &self.shards {
             data.extend(st.unpack());
         }
 
         // apply full domain hashing
 
-        let mut data = fdh(data, FDH_ROUNDS, true);
+        let mut data = aont(self.prf, self.segment * self.shard_size, 0, data, true);
         data.truncate(self.length);
         data
     }
+
+    /// Recovers the contents,
+    /// fails with Error::Encrypted if the file is encrypted (see File::unpack_with_key)
+    /// and with Error::Malformed if the contents do not decompress.
+    pub fn unpack(&self) -> Result<Vec<u8>, Error> {
+        if self.seal.is_some() {
+            return Err(Error::Encrypted);
+        }
+        let data = self.stored();
+        if self.compression != Compression::None {
+            return self.compression.decompress(&data[..], self.original);
+        }
+        Ok(data)
+    }
+
+    /// Recovers the contents of an encrypted file,
+    /// failing if the key is wrong or the contents have been modified.
+    /// Files which are not encrypted are unpacked as by File::unpack.
+    pub fn unpack_with_key(&self, key: &FileKey) -> Result<Vec<u8>, Error> {
+        match &self.seal {
+            Some(seal) => {
+                let data = key.open(&self.stored()[..], seal)?;
+                self.compression.decompress(&data[..], self.original)
+            }
+            None => self.unpack(),
+        }
+    }
+
+    /// Recovers the contents and checks them against the SHA-256 digest recorded in the header,
+    /// failing with Error::Digest if they differ (e.g. a wrong or corrupted shard was used).
+    /// Encrypted files have no digest (they are authenticated by File::unpack_with_key)
+    /// and fail with Error::Encrypted.
+    pub fn unpack_verified(&self) -> Result<Vec<u8>, Error> {
+        // a corrupted shard may also prevent decompression
+        let data = self.unpack().map_err(|e| match e {
+            Error::Malformed => Error::Digest,
+            e => e,
+        })?;
+        match self.digest {
+            Some(digest) if digest == sha256(&data[..]) => Ok(data),
+            _ => Err(Error::Digest),
+        }
+    }
+
+    // shards per unit of the transform
+    fn segment_shards(&self) -> usize {
+        if self.segment == 0 {
+            self.shards.len()
+        } else {
+            self.segment
+        }
+    }
+
+    // inverts the transform on segments first..=last
+    // (a file that is not segmented is a single segment)
+    fn decode_segments(&self, first: usize, last: usize) -> Vec<u8> {
+        let n = self.segment_shards();
+        let lo = first * n;
+        let hi = ((last + 1) * n).min(self.shards.len());
+        let mut data = Vec::with_capacity((hi - lo) * self.shard_size);
+        for st in &self.shards[lo..hi] {
+            data.extend(st.unpack());
+        }
+        aont(self.prf, self.segment * self.shard_size, first, data, true)
+    }
+
+    /// Returns the bytes in [offset, offset + len) of the file
+    /// (the range is truncated at the end of the file),
+    /// inverting the transform only on the segments covering the range.
+    /// A compressed file is decompressed as a whole.
+    /// Fails with Error::Encrypted if the file is encrypted.
+    pub fn read_range(&self, offset: usize, len: usize) -> Result<Vec<u8>, Error> {
+        if self.seal.is_some() {
+            return Err(Error::Encrypted);
+        }
+        if self.compression != Compression::None {
+            return Ok(slice_range(self.unpack()?, offset, len));
+        }
+
+        let end = offset.saturating_add(len).min(self.length);
+        if offset >= end {
+            return Ok(vec![]);
+        }
+
+        let seg_size = self.segment_shards() * self.shard_size;
+        let first = offset / seg_size;
+        let mut data = self.decode_segments(first, (end - 1) / seg_size);
+        data.truncate(end - first * seg_size);
+        Ok(data.split_off(offset - first * seg_size))
+    }
+
+    /// Returns a reader over the contents of the file,
+    /// which inverts the transform one segment at a time as it is read
+    /// (the whole file on the first read, if not segmented).
+    /// A compressed file is decompressed as a whole when the reader is created.
+    /// Fails with Error::Encrypted if the file is encrypted.
+    pub fn reader(&self) -> Result<FileReader<'_>, Error> {
+        if self.seal.is_some() {
+            return Err(Error::Encrypted);
+        }
+        let contents = match self.compression {
+            Compression::None => None,
+            _ => Some(self.unpack()?),
+        };
+        Ok(FileReader {
+            file: self,
+            pos: 0,
+            cache: None,
+            contents,
+        })
+    }
+
+    /// Recovers the bytes in [offset, offset + len) of the file
+    /// (the range is truncated at the end of the file).
+    ///
+    /// For a segmented file only the data shards of the segments covering the range are used,
+    /// if any of them are missing (or the file is not segmented)
+    /// the file is reconstructed from all the given shards.
+    /// Fails with Error::Encrypted if the file is encrypted.
+    pub fn reconstruct_range(
+        header: &Header,
+        shards: &[Shard],
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<u8>, Error> {
+        if header.seal.is_some() {
+            return Err(Error::Encrypted);
+        }
+
+        // compressed: every shard is needed
+        if header.compression != Compression::None {
+            return File::reconstruct(header, shards)?.read_range(offset, len);
+        }
+
+        let end = offset.saturating_add(len).min(header.length);
+        if offset >= end {
+            return Ok(vec![]);
+        }
+
+        // whole file transform: every shard is needed
+        if header.segment == 0 {
+            return File::reconstruct(header, shards)?.read_range(offset, len);
+        }
+
+        // data shards [lo, hi) of the covering segments
+        let size = header.shard_size;
+        let seg_size = header.segment.checked_mul(size).ok_or(Error::Malformed)?;
+        let first = offset / seg_size;
+        let lo = first * header.segment;
+        let hi = ((end - 1) / seg_size)
+            .checked_add(1)
+            .and_then(|n| n.checked_mul(header.segment))
+            .ok_or(Error::Malformed)?
+            .min(header.shards());
+
+        // the covering shards given (the header is untrusted, so memory is only
+        // allocated for the shards actually provided)
+        let mut covering: BTreeMap<usize, &Shard> = BTreeMap::new();
+        for s in shards {
+            let idx = s.idx as usize;
+            if lo <= idx && idx < hi {
+                if s.coords.len() * 2 != size {
+                    return Err(Error::Reconstruct);
+                }
+                covering.insert(idx, s);
+            }
+        }
+
+        // fall back to erasure decoding
+        if covering.len() != hi - lo {
+            return File::reconstruct(header, shards)?.read_range(offset, len);
+        }
+        let mut data = Vec::with_capacity(covering.len() * size);
+        for s in covering.values() {
+            data.extend_from_slice(s.as_bytes());
+        }
+
+        // invert the transform of the covering segments
+        let mut data = aont(header.prf, seg_size, first, data, true);
+        data.truncate(end - first * seg_size);
+        Ok(data.split_off(offset - first * seg_size))
+    }
+}
+
+// bytes [offset, offset + len) of data, truncated at the end
+pub(crate) fn slice_range(mut data: Vec<u8>, offset: usize, len: usize) -> Vec<u8> {
+    let end = offset.saturating_add(len).min(data.len());
+    data.truncate(end);
+    data.split_off(offset.min(end))
+}
+
+/// Read + Seek view of a File, see File::reader
+pub struct FileReader<'a> {
+    file: &'a File,
+    pos: u64,
+    cache: Option<(usize, Vec<u8>)>, // last decoded segment
+    contents: Option<Vec<u8>>,       // decompressed contents (if compressed)
+}
+
+impl Read for FileReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = self.file.original as u64; // length of the contents
+        if self.pos >= length || buf.is_empty() {
+            return Ok(0);
+        }
+
+        // compressed: copy from the decompressed contents
+        if let Some(data) = &self.contents {
+            let start = self.pos as usize;
+            let n = buf.len().min(data.len() - start);
+            buf[..n].copy_from_slice(&data[start..start + n]);
+            self.pos += n as u64;
+            return Ok(n);
+        }
+
+        // decode the segment containing the position
+        let seg_size = (self.file.segment_shards() * self.file.shard_size) as u64;
+        let idx = (self.pos / seg_size) as usize;
+        if !matches!(self.cache, Some((cached, _)) if cached == idx) {
+            self.cache = Some((idx, self.file.decode_segments(idx, idx)));
+        }
+        let data = &self.cache.as_ref().unwrap().1;
+
+        // copy until the end of the segment (or file)
+        let start = self.pos - idx as u64 * seg_size;
+        let end = seg_size.min(length - idx as u64 * seg_size);
+        let n = buf.len().min((end - start) as usize);
+        buf[..n].copy_from_slice(&data[start as usize..start as usize + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for FileReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::End(delta) => (self.file.original as u64, delta),
+            SeekFrom::Current(delta) => (self.pos, delta),
+        };
+        match base.checked_add_signed(delta) {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
 }
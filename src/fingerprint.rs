@@ -0,0 +1,50 @@
+use std::fmt;
+
+use super::bn::BigNum;
+use super::primitives::Sha256;
+
+const FINGERPRINT_SIZE: usize = 32;
+
+// domain separation for the fingerprint hash
+const FINGERPRINT_TAG: &[u8] = b"portage-key-fingerprint";
+
+/// Identifies the key pair used to encode a shard:
+/// SHA-256 of the canonical (minimal big-endian) encoding of the modulus.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Fingerprint([u8; FINGERPRINT_SIZE]);
+
+impl Fingerprint {
+    pub const SIZE: usize = FINGERPRINT_SIZE;
+
+    pub(crate) fn of_modulus(n: &BigNum) -> Self {
+        let n = n.to_vec();
+        let mut hsh = Sha256::new();
+        hsh.update(FINGERPRINT_TAG);
+        hsh.update(&(n.len() as u32).to_be_bytes());
+        hsh.update(&n[..]);
+        Fingerprint(hsh.finish())
+    }
+
+    pub fn from_bytes(bytes: [u8; FINGERPRINT_SIZE]) -> Self {
+        Fingerprint(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; FINGERPRINT_SIZE] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fingerprint({})", self)
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+
+use super::misc::Reader;
+use super::primitives::Sha256;
+use super::{EncodedShard, Error, File, Header, Shard, MODULUS_BYTES};
+
+const ID_SIZE: usize = 32;
+
+// domain separation for the identifiers
+const SHARD_TAG: &[u8] = b"portage-shard";
+const ENCODED_TAG: &[u8] = b"portage-encoded-shard";
+
+/// Content hash identifying a shard or an encoded shard:
+/// SHA-256 of its index, (replica,) PRF and contents (domain separated).
+/// The key fingerprint attached to an encoded shard is not part of the identifier.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShardId([u8; ID_SIZE]);
+
+impl ShardId {
+    pub const SIZE: usize = ID_SIZE;
+
+    pub fn from_bytes(bytes: [u8; ID_SIZE]) -> Self {
+        ShardId(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; ID_SIZE] {
+        &self.0
+    }
+
+    fn read(rd: &mut Reader) -> Result<Self, Error> {
+        let mut id = [0u8; ID_SIZE];
+        id.copy_from_slice(rd.take(ID_SIZE)?);
+        Ok(ShardId(id))
+    }
+}
+
+impl fmt::Display for ShardId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ShardId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ShardId({})", self)
+    }
+}
+
+impl Shard {
+    /// Returns the content hash of the shard,
+    /// equal for the same codeword position of deterministically split files
+    pub fn id(&self) -> ShardId {
+        let mut hsh = Sha256::new();
+        hsh.update(SHARD_TAG);
+        hsh.update(&self.idx.to_be_bytes());
+        hsh.update(&[self.prf.id()]);
+        hsh.update(self.as_bytes());
+        ShardId(hsh.finish())
+    }
+}
+
+impl EncodedShard {
+    /// Returns the content hash of the encoded shard
+    /// (the same whether or not a key fingerprint is attached)
+    pub fn id(&self) -> ShardId {
+        let mut hsh = Sha256::new();
+        hsh.update(ENCODED_TAG);
+        hsh.update(&self.idx.to_be_bytes());
+        hsh.update(&self.replica.to_be_bytes());
+        hsh.update(&[self.prf.id()]);
+        for block in self.blocks.iter() {
+            for s in block.s.iter() {
+                hsh.update(&s.to_vec_padded(MODULUS_BYTES).unwrap()[..]);
+            }
+        }
+        ShardId(hsh.finish())
+    }
+}
+
+/// Entry of a ShardManifest
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub index: u16,
+    pub plain: ShardId,           // hash of the shard
+    pub encoded: Option<ShardId>, // hash of the encoded shard (once recorded)
+    pub replica: u32,             // replica of the encoded shard
+}
+
+/// Lists the content hashes of the shards of a file,
+/// such that shards can be stored and looked up by hash
+/// (unrelated to the manifest of key fingerprints in the header, see Header::manifest)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShardManifest {
+    entries: Vec<ManifestEntry>,
+}
+
+impl ShardManifest {
+    const VERSION: u8 = 1;
+
+    /// Creates a manifest of (not yet encoded) shards
+    pub fn from_shards(shards: &[Shard]) -> Self {
+        ShardManifest {
+            entries: shards
+                .iter()
+                .map(|s| ManifestEntry {
+                    index: s.idx,
+                    plain: s.id(),
+                    encoded: None,
+                    replica: 0,
+                })
+                .collect(),
+        }
+    }
+
+    /// Records the hash of an encoded shard,
+    /// a replica of a shard which is already recorded adds another entry.
+    /// Fails with Error::NotFound if the manifest does not contain the shard.
+    pub fn record(&mut self, shard: &EncodedShard) -> Result<(), Error> {
+        let pos = self
+            .entries
+            .iter()
+            .position(|e| e.index == shard.idx)
+            .ok_or(Error::NotFound)?;
+        let entry = ManifestEntry {
+            index: shard.idx,
+            plain: self.entries[pos].plain,
+            encoded: Some(shard.id()),
+            replica: shard.replica,
+        };
+        if self.entries[pos].encoded.is_none() {
+            self.entries[pos] = entry;
+        } else if !self.entries.contains(&entry) {
+            self.entries.insert(pos + 1, entry);
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// Looks up the entry with the given (plain or encoded) hash
+    pub fn find(&self, id: &ShardId) -> Option<&ManifestEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.plain == *id || e.encoded.as_ref() == Some(id))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut ser = Vec::with_capacity(1 + 4 + self.entries.len() * (2 + 2 * ID_SIZE + 1 + 4));
+        ser.push(Self::VERSION);
+        ser.extend(&(self.entries.len() as u32).to_be_bytes());
+        for e in self.entries.iter() {
+            ser.extend(&e.index.to_be_bytes());
+            ser.extend(e.plain.as_bytes());
+            match &e.encoded {
+                Some(id) => {
+                    ser.push(1);
+                    ser.extend(id.as_bytes());
+                }
+                None => ser.push(0),
+            }
+            ser.extend(&e.replica.to_be_bytes());
+        }
+        ser
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let mut rd = Reader::new(bytes);
+        if rd.u8()? != Self::VERSION {
+            return Err(Error::Malformed);
+        }
+        let count = rd.u32()? as usize;
+        let mut entries = Vec::with_capacity(count.min(bytes.len()));
+        for _ in 0..count {
+            let index = rd.u16()?;
+            let plain = ShardId::read(&mut rd)?;
+            let encoded = match rd.u8()? {
+                0 => None,
+                1 => Some(ShardId::read(&mut rd)?),
+                _ => return Err(Error::Malformed),
+            };
+            let replica = rd.u32()?;
+            entries.push(ManifestEntry {
+                index,
+                plain,
+                encoded,
+                replica,
+            });
+        }
+        rd.finish()?;
+        Ok(ShardManifest { entries })
+    }
+}
+
+impl File {
+    /// Like File::shards, additionally returning the content hashes of the shards
+    /// (see ShardManifest::record for the hashes of the encoded shards).
+    /// The hashes are kept out of the header: they are only known once the shards exist
+    /// and are not needed to reconstruct the file.
+    pub fn shards_with_ids(
+        &self,
+        expansion: usize,
+    ) -> Result<(Header, Vec<Shard>, ShardManifest), Error> {
+        let (header, shards) = self.shards(expansion)?;
+        let manifest = ShardManifest::from_shards(&shards[..]);
+        Ok((header, shards, manifest))
+    }
+}
@@ -0,0 +1,151 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use zeroize::Zeroizing;
+
+use super::primitives::{aes_256_gcm_open, aes_256_gcm_seal, rand_bytes, scrypt};
+use super::EncodingKey;
+use super::Error;
+
+/* Encrypted key file
+ *
+ * magic || version || log2(N) || r || p || salt || nonce || tag || ciphertext
+ *
+ * The key is derived from the password using scrypt,
+ * the serialized encoding key is sealed using AES-256-GCM
+ * with everything preceding the tag as associated data.
+ */
+const MAGIC: [u8; 8] = *b"PORTAGEK";
+const VERSION: u8 = 1;
+
+// scrypt parameters (N = 2^15, r = 8, p = 1)
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+const SCRYPT_MAXMEM: u64 = 256 * 1024 * 1024;
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const KEY_SIZE: usize = 32;
+
+const AAD_SIZE: usize = MAGIC.len() + 1 + 1 + 4 + 4 + SALT_SIZE + NONCE_SIZE;
+const HEADER_SIZE: usize = AAD_SIZE + TAG_SIZE;
+
+fn derive(
+    password: &[u8],
+    salt: &[u8],
+    log_n: u8,
+    r: u32,
+    p: u32,
+) -> Result<Zeroizing<[u8; KEY_SIZE]>, Error> {
+    // reject parameters which would make loading unreasonably expensive
+    if log_n == 0 || log_n > 20 || r == 0 || r > 32 || p == 0 || p > 16 {
+        return Err(Error::Malformed);
+    }
+
+    // memory used by scrypt (as accounted by OpenSSL)
+    let mem = 128 * r as u64 * ((1u64 << log_n) + 2 + p as u64);
+    if mem > SCRYPT_MAXMEM {
+        return Err(Error::Malformed);
+    }
+
+    let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+    scrypt(password, salt, log_n, r, p, &mut key[..])?;
+    Ok(key)
+}
+
+impl EncodingKey {
+    /// Serializes the encoding key, encrypted under a password
+    pub fn serialize_encrypted(&self, password: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut salt = [0u8; SALT_SIZE];
+        let mut nonce = [0u8; NONCE_SIZE];
+        rand_bytes(&mut salt)?;
+        rand_bytes(&mut nonce)?;
+
+        // associated data
+        let mut ser = Vec::with_capacity(HEADER_SIZE);
+        ser.extend(&MAGIC);
+        ser.push(VERSION);
+        ser.push(SCRYPT_LOG_N);
+        ser.extend(&SCRYPT_R.to_be_bytes());
+        ser.extend(&SCRYPT_P.to_be_bytes());
+        ser.extend(&salt);
+        ser.extend(&nonce);
+        debug_assert_eq!(ser.len(), AAD_SIZE);
+
+        // seal the serialized key
+        let key = derive(password, &salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)?;
+        let mut tag = [0u8; TAG_SIZE];
+        let ct = aes_256_gcm_seal(&key[..], &nonce, &ser[..], &self.serialize()[..], &mut tag)?;
+
+        ser.extend(&tag);
+        ser.extend(ct);
+        Ok(ser)
+    }
+
+    /// Deserializes an encoding key encrypted under a password
+    pub fn deserialize_encrypted(bytes: &[u8], password: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_SIZE || bytes[..MAGIC.len()] != MAGIC {
+            return Err(Error::Malformed);
+        }
+
+        // parse header
+        let mut pos = MAGIC.len();
+        if bytes[pos] != VERSION {
+            return Err(Error::Malformed);
+        }
+        let log_n = bytes[pos + 1];
+        pos += 2;
+
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&bytes[pos..pos + 4]);
+        let r = u32::from_be_bytes(word);
+        word.copy_from_slice(&bytes[pos + 4..pos + 8]);
+        let p = u32::from_be_bytes(word);
+        pos += 8;
+
+        let salt = &bytes[pos..pos + SALT_SIZE];
+        let nonce = &bytes[pos + SALT_SIZE..AAD_SIZE];
+        let tag = &bytes[AAD_SIZE..HEADER_SIZE];
+
+        // open the serialized key
+        let key = derive(password, salt, log_n, r, p)?;
+        let pt = aes_256_gcm_open(
+            &key[..],
+            nonce,
+            &bytes[..AAD_SIZE],
+            &bytes[HEADER_SIZE..],
+            tag,
+        )
+        .map(Zeroizing::new)?;
+
+        EncodingKey::deserialize(&pt[..])
+    }
+
+    /// Writes the encoding key to a file, encrypted under a password
+    pub fn save_encrypted<P: AsRef<Path>>(&self, path: P, password: &[u8]) -> Result<(), Error> {
+        let ser = self.serialize_encrypted(password)?;
+
+        let mut opts = fs::OpenOptions::new();
+        opts.write(true).create(true).truncate(true);
+
+        // only readable by the owner
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            opts.mode(0o600);
+        }
+
+        let mut file = opts.open(path)?;
+        file.write_all(&ser[..])?;
+        Ok(())
+    }
+
+    /// Reads an encoding key from a file encrypted under a password
+    pub fn load_encrypted<P: AsRef<Path>>(path: P, password: &[u8]) -> Result<Self, Error> {
+        let bytes = fs::read(path)?;
+        Self::deserialize_encrypted(&bytes[..], password)
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use super::{DecodingKey, EncodedShard, Error, Fingerprint, Header, Shard};
+
+/// Collection of decoding keys indexed by fingerprint,
+/// used to decode files whose shards were encoded by different encoders.
+#[derive(Clone, Default)]
+pub struct KeyRing {
+    keys: HashMap<Fingerprint, DecodingKey>,
+}
+
+impl KeyRing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a decoding key, replacing any existing key with the same fingerprint
+    pub fn insert(&mut self, key: DecodingKey) {
+        self.keys.insert(key.fingerprint(), key);
+    }
+
+    pub fn get(&self, fp: &Fingerprint) -> Option<&DecodingKey> {
+        self.keys.get(fp)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Decodes a shard using the key named by the shard itself,
+    /// or otherwise the key assigned to the shard by the header manifest.
+    pub fn decode(&mut self, header: &Header, s: &mut EncodedShard) -> Result<(), Error> {
+        let fp = match (s.key, header.shard_fingerprint(s.idx)) {
+            (Some(k), Some(h)) if k != *h => return Err(Error::KeyMismatch),
+            (Some(k), _) => k,
+            (None, Some(h)) => *h,
+            (None, None) => return Err(Error::UnknownKey),
+        };
+        let key = self.keys.get_mut(&fp).ok_or(Error::UnknownKey)?;
+        key.decode(s)
+    }
+
+    /// Decodes every shard, ready for File::reconstruct
+    pub fn decode_shards(
+        &mut self,
+        header: &Header,
+        shards: Vec<EncodedShard>,
+    ) -> Result<Vec<Shard>, Error> {
+        shards
+            .into_iter()
+            .map(|mut s| {
+                self.decode(header, &mut s)?;
+                s.unpack()
+            })
+            .collect()
+    }
+}
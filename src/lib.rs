@@ -1,38 +1,84 @@
 #![feature(test)]
+// unsafe code is confined to the FFI of the OpenSSL arithmetic backend
+#![deny(unsafe_code)]
 
 extern crate test;
 
+#[cfg(not(any(feature = "openssl", feature = "rust-crypto")))]
+compile_error!("enable either the \"openssl\" or the \"rust-crypto\" feature");
+
+mod archive;
+mod bn;
+mod bundle;
+mod compress;
+mod crypt;
+mod error;
 mod file;
+mod fingerprint;
+mod id;
+mod keyfile;
+mod keyring;
 mod misc;
+mod prf;
+mod primitives;
 mod rs;
 mod rsa;
+#[cfg(feature = "serde")]
+mod serialize;
+mod store;
+mod stripe;
 
 #[cfg(test)]
 mod tests;
 
-use openssl::bn::BigNum;
+use std::collections::BTreeMap;
 use std::fmt;
-use std::mem::MaybeUninit;
 
+use bn::BigNum;
+use crypt::Seal;
+use misc::Reader;
+
+pub use archive::{ArchiveReader, ArchiveWriter};
+pub use bundle::{Bundle, BundleEntry};
+pub use compress::Compression;
+pub use crypt::FileKey;
+pub use error::Error;
+pub use file::FileReader;
+pub use fingerprint::Fingerprint;
+pub use id::{ManifestEntry, ShardId, ShardManifest};
+pub use keyring::KeyRing;
+pub use prf::{Blake3Xof, Prf, PrfKind, Sha256AesCtr, Shake256};
 pub use rsa::{DecodingKey, EncodingKey};
+pub use store::ShardStore;
+pub use stripe::RootManifest;
+pub use zeroize::Zeroizing;
 
 // group size
 const PRIME_SIZE: usize = 1025;
 const MODULUS_SIZE: usize = 2 * PRIME_SIZE;
+const MODULUS_BYTES: usize = MODULUS_SIZE.div_ceil(8);
 
 // message always slightly smaller to ensure that it is contained
 const BLOCK_HALF_SIZE_BITS: usize = 8 * (MODULUS_SIZE / 8);
 const BLOCK_HALF_SIZE: usize = BLOCK_HALF_SIZE_BITS / 8;
-const BLOCK_SIZE: usize = 2 * BLOCK_HALF_SIZE;
 
-const SHARD_SIZE: usize = 1024; // each shard is 1KB
-const SHARD_ELEMS: usize = SHARD_SIZE / 2; // shard elements are GF(2^16)
-const SHARD_BLOCKS: usize = SHARD_SIZE / BLOCK_SIZE;
+/// Size of an encode block, shard sizes are multiples of it
+pub const BLOCK_SIZE: usize = 2 * BLOCK_HALF_SIZE;
+
+/// Default shard size (1KB)
+pub const SHARD_SIZE: usize = 1024;
+
+/// Maximum number of shards (data and parity) of a file, shard indices are u16
+pub const MAX_SHARDS: usize = 1 << 16;
 
-#[derive(Copy, Clone)]
+// SHA-256 of the contents
+const DIGEST_SIZE: usize = 32;
+
+#[derive(Clone)]
 pub struct Shard {
     pub(crate) idx: u16,
-    pub(crate) coords: [[u8; 2]; SHARD_SIZE / 2],
+    pub(crate) prf: PrfKind,         // PRF used by the encoding
+    pub(crate) coords: Vec<[u8; 2]>, // shard elements are GF(2^16)
 }
 
 #[derive(Debug)]
@@ -40,39 +86,275 @@ pub(crate) struct EncodeBlock {
     pub(crate) s: [BigNum; 2],
 }
 
-impl Default for EncodeBlock {
-    fn default() -> Self {
-        EncodeBlock {
-            s: [BigNum::new().unwrap(), BigNum::new().unwrap()],
-        }
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct EncodedShard {
     pub(crate) idx: u16,
-    pub(crate) blocks: [EncodeBlock; SHARD_BLOCKS],
+    pub(crate) prf: PrfKind,
+    pub(crate) replica: u32, // distinguishes encodings of the same shard
+    pub(crate) key: Option<Fingerprint>, // key used for encoding
+    pub(crate) blocks: Vec<EncodeBlock>,
 }
 
 #[derive(Debug)]
 pub struct File {
     pub(crate) length: usize,
+    pub(crate) prf: PrfKind,
+    pub(crate) shard_size: usize,
+    pub(crate) segment: usize,
+    pub(crate) seal: Option<Seal>,
+    pub(crate) compression: Compression,
+    pub(crate) original: usize, // length before compression
+    pub(crate) digest: Option<[u8; DIGEST_SIZE]>,
     pub(crate) shards: Vec<Shard>,
 }
 
+/// Parameter set used when splitting a file
+#[derive(Clone, Debug)]
+pub struct Params {
+    /// PRF used for full-domain hashing and encoding
+    pub prf: PrfKind,
+
+    /// Size of every shard in bytes (SHARD_SIZE by default),
+    /// must be a positive multiple of BLOCK_SIZE
+    pub shard_size: usize,
+
+    /// Number of shards in each all-or-nothing segment:
+    /// 0 applies the transform to the whole file,
+    /// otherwise byte ranges can be recovered from the shards of the covering segments
+    pub segment: u16,
+
+    /// Encrypts the file (AES-256-GCM) before splitting,
+    /// such that the contents can only be recovered using File::unpack_with_key
+    pub key: Option<FileKey>,
+
+    /// Compresses the input before splitting (and encryption)
+    pub compression: Compression,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            prf: Default::default(),
+            shard_size: SHARD_SIZE,
+            segment: 0,
+            key: None,
+            compression: Default::default(),
+        }
+    }
+}
+
 pub struct Header {
-    pub(crate) length: usize, // length of file
+    pub(crate) length: usize,                        // length of file
+    pub(crate) prf: PrfKind,                         // PRF used by the shards
+    pub(crate) shard_size: usize,                    // size of every shard in bytes
+    pub(crate) segment: usize,                       // shards per segment (0: whole file)
+    pub(crate) seal: Option<Seal>,                   // nonce and tag if encrypted
+    pub(crate) compression: Compression,             // compression of the input
+    pub(crate) original: usize,                      // length before compression
+    pub(crate) digest: Option<[u8; DIGEST_SIZE]>,    // SHA-256 of the contents (if not encrypted)
+    pub(crate) key: Option<Fingerprint>,             // key used for encoding
+    pub(crate) manifest: BTreeMap<u16, Fingerprint>, // per-shard keys (overrides key)
+}
+
+fn write_optional_fingerprint(ser: &mut Vec<u8>, key: &Option<Fingerprint>) {
+    match key {
+        Some(fp) => {
+            ser.push(1);
+            ser.extend(fp.as_bytes());
+        }
+        None => ser.push(0),
+    }
+}
+
+fn read_fingerprint(rd: &mut Reader) -> Result<Fingerprint, Error> {
+    let mut fp = [0u8; Fingerprint::SIZE];
+    fp.copy_from_slice(rd.take(Fingerprint::SIZE)?);
+    Ok(Fingerprint::from_bytes(fp))
+}
+
+fn read_optional_fingerprint(rd: &mut Reader) -> Result<Option<Fingerprint>, Error> {
+    match rd.u8()? {
+        0 => Ok(None),
+        1 => Ok(Some(read_fingerprint(rd)?)),
+        _ => Err(Error::Malformed),
+    }
 }
 
 impl Header {
+    const VERSION: u8 = 1;
+
+    /// Returns the fingerprint of the key used to encode the shards (if recorded)
+    pub fn fingerprint(&self) -> Option<&Fingerprint> {
+        self.key.as_ref()
+    }
+
+    /// Records the fingerprint of the key used to encode the shards
+    pub fn set_fingerprint(&mut self, key: Fingerprint) {
+        self.key = Some(key);
+    }
+
+    /// Returns the fingerprint of the key used to encode a particular shard:
+    /// the entry in the manifest if present, otherwise the key for the whole file
+    pub fn shard_fingerprint(&self, idx: u16) -> Option<&Fingerprint> {
+        self.manifest.get(&idx).or(self.key.as_ref())
+    }
+
+    /// Records in the manifest that a shard was encoded by a different encoder
+    pub fn set_shard_fingerprint(&mut self, idx: u16, key: Fingerprint) {
+        self.manifest.insert(idx, key);
+    }
+
+    /// Returns the size of every shard in bytes
+    pub fn shard_size(&self) -> usize {
+        self.shard_size
+    }
+
+    /// Returns the number of shards in each all-or-nothing segment
+    /// (0 if the transform covers the whole file)
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    /// Returns true if the file was encrypted before splitting
+    pub fn encrypted(&self) -> bool {
+        self.seal.is_some()
+    }
+
+    /// Returns the compression applied before splitting
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Returns the length of the contents in bytes (before compression / encryption)
+    pub fn original_length(&self) -> usize {
+        self.original
+    }
+
+    /// Returns the length in bytes of what is split into shards:
+    /// the contents after compression / encryption (see Header::original_length)
+    pub fn stored_length(&self) -> usize {
+        self.length
+    }
+
+    /// Returns the stored length, same as Header::stored_length
+    /// (not the length of the contents, see Header::original_length)
+    pub fn length(&self) -> usize {
+        self.stored_length()
+    }
+
+    /// Returns the SHA-256 digest of the contents,
+    /// not recorded for encrypted files (which are authenticated by the encryption)
+    pub fn digest(&self) -> Option<&[u8; DIGEST_SIZE]> {
+        self.digest.as_ref()
+    }
+
+    /// Returns the manifest mapping shard indices to keys
+    /// (unrelated to the content hashes of ShardManifest)
+    pub fn manifest(&self) -> &BTreeMap<u16, Fingerprint> {
+        &self.manifest
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut ser = Vec::with_capacity(
+            1 + 8
+                + 1
+                + 4
+                + 2
+                + 1
+                + Seal::SIZE
+                + 1
+                + 8
+                + 1
+                + DIGEST_SIZE
+                + 1
+                + Fingerprint::SIZE
+                + 4
+                + self.manifest.len() * (2 + Fingerprint::SIZE),
+        );
+        ser.push(Self::VERSION);
+        ser.extend(&(self.length as u64).to_be_bytes());
+        ser.push(self.prf.id());
+        ser.extend(&(self.shard_size as u32).to_be_bytes());
+        ser.extend(&(self.segment as u16).to_be_bytes());
+        Seal::write_optional(&mut ser, &self.seal);
+        ser.push(self.compression.id());
+        if self.compression != Compression::None {
+            ser.extend(&(self.original as u64).to_be_bytes());
+        }
+        match &self.digest {
+            Some(digest) => {
+                ser.push(1);
+                ser.extend(digest);
+            }
+            None => ser.push(0),
+        }
+        write_optional_fingerprint(&mut ser, &self.key);
+        // u32: the manifest may hold all MAX_SHARDS (u16) indices
+        ser.extend(&(self.manifest.len() as u32).to_be_bytes());
+        for (idx, fp) in self.manifest.iter() {
+            ser.extend(&idx.to_be_bytes());
+            ser.extend(fp.as_bytes());
+        }
+        ser
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let mut rd = Reader::new(bytes);
+        if rd.u8()? != Self::VERSION {
+            return Err(Error::Malformed);
+        }
+        let length = rd.usize()?;
+        let prf = PrfKind::from_id(rd.u8()?)?;
+        let shard_size = rd.u32()? as usize;
+        if shard_size == 0 || !shard_size.is_multiple_of(BLOCK_SIZE) {
+            return Err(Error::Malformed);
+        }
+        let segment = rd.u16()? as usize;
+        let seal = Seal::read_optional(&mut rd)?;
+        let compression = Compression::from_id(rd.u8()?)?;
+        let original = match compression {
+            Compression::None => length,
+            _ => rd.usize()?,
+        };
+        let digest = match rd.u8()? {
+            0 => None,
+            1 => {
+                let mut digest = [0u8; DIGEST_SIZE];
+                digest.copy_from_slice(rd.take(DIGEST_SIZE)?);
+                Some(digest)
+            }
+            _ => return Err(Error::Malformed),
+        };
+        let key = read_optional_fingerprint(&mut rd)?;
+        let mut manifest = BTreeMap::new();
+        let count = rd.u32()? as usize;
+        if count > MAX_SHARDS {
+            return Err(Error::Malformed);
+        }
+        for _ in 0..count {
+            let idx = rd.u16()?;
+            if manifest.insert(idx, read_fingerprint(&mut rd)?).is_some() {
+                return Err(Error::Malformed);
+            }
+        }
+        rd.finish()?;
+        Ok(Header {
+            length,
+            prf,
+            shard_size,
+            segment,
+            seal,
+            compression,
+            original,
+            digest,
+            key,
+            manifest,
+        })
+    }
+
     /// Returns the number of shards needed to reconstruct the file
     pub fn shards(&self) -> usize {
-        let n = self.length / SHARD_SIZE;
-        if self.length % SHARD_SIZE != 0 {
-            n + 1
-        } else {
-            n
-        }
+        self.length.div_ceil(self.shard_size)
     }
 }
 
@@ -80,88 +362,202 @@ impl fmt::Debug for Header {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Header {{ length = {}, data-shards (dimension) = {} }}",
+            "Header {{ length = {}, data-shards (dimension) = {}, prf = {:?}, shard-size = {}, segment = {}, encrypted = {}, compression = {:?}, original-length = {}, key = {:?}, manifest = {:?} }}",
             self.length,
-            self.shards()
+            self.shards(),
+            self.prf,
+            self.shard_size,
+            self.segment,
+            self.encrypted(),
+            self.compression,
+            self.original,
+            self.key,
+            self.manifest
         )
     }
 }
 
 impl Shard {
-    pub(crate) fn new(idx: u16, bytes: &[u8]) -> Self {
-        debug_assert_eq!(bytes.len(), SHARD_SIZE);
-        debug_assert_eq!(bytes.len(), SHARD_ELEMS * 2);
+    pub(crate) fn new(idx: u16, prf: PrfKind, bytes: &[u8]) -> Self {
+        debug_assert!(!bytes.is_empty());
+        debug_assert_eq!(bytes.len() % BLOCK_SIZE, 0);
 
-        let mut shard = Shard {
+        Shard {
             idx,
-            coords: [Default::default(); SHARD_ELEMS],
-        };
+            prf,
+            coords: bytes.chunks_exact(2).map(|c| [c[0], c[1]]).collect(),
+        }
+    }
 
-        for i in 0..shard.coords.len() {
-            shard.coords[i][0] = bytes[i * 2];
-            shard.coords[i][1] = bytes[i * 2 + 1];
+    /// Creates a shard from its contents (e.g. as returned by Shard::as_bytes),
+    /// the length must be a positive multiple of BLOCK_SIZE.
+    /// The shard uses the default PRF, see Shard::set_prf.
+    pub fn from_bytes(idx: u16, bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.is_empty() || !bytes.len().is_multiple_of(BLOCK_SIZE) {
+            return Err(Error::Malformed);
         }
-        shard
+        Ok(Self::new(idx, Default::default(), bytes))
+    }
+
+    /// Returns the index of the shard in the codeword
+    pub fn index(&self) -> u16 {
+        self.idx
+    }
+
+    /// Returns the PRF used when encoding the shard
+    pub fn prf(&self) -> PrfKind {
+        self.prf
+    }
+
+    /// Sets the PRF used when encoding the shard (that of the file)
+    pub fn set_prf(&mut self, prf: PrfKind) {
+        self.prf = prf;
+    }
+
+    /// Returns the contents of the shard
+    pub fn as_bytes(&self) -> &[u8] {
+        self.coords.as_flattened()
     }
 
     pub(crate) fn unpack(&self) -> Vec<u8> {
-        let mut bytes: Vec<u8> = Vec::with_capacity(self.coords.len() * 2);
-        for c in self.coords.iter() {
-            bytes.push(c[0]);
-            bytes.push(c[1]);
-        }
-        bytes
+        self.as_bytes().to_vec()
     }
 
     pub fn pack(&self) -> EncodedShard {
+        self.pack_replica(0)
+    }
+
+    /// Packs the shard for encoding as a particular replica,
+    /// distinct replicas of the same shard have unrelated encodings
+    pub fn pack_replica(&self, replica: u32) -> EncodedShard {
         // unpack GF(2^16) elements to bytes
         let bytes = self.unpack();
 
-        // split into encode blocks
-        let mut blocks: [EncodeBlock; SHARD_BLOCKS] =
-            unsafe { MaybeUninit::zeroed().assume_init() };
-
-        for i in 0..SHARD_BLOCKS {
-            // calculate byte ranges for block
-            let l = i * BLOCK_SIZE;
-            let m = l + BLOCK_HALF_SIZE;
-            let r = (i + 1) * BLOCK_SIZE;
-
-            // pack bytes into bignum integers
-            let s0 = BigNum::from_slice(&bytes[l..m]).unwrap();
-            let s1 = BigNum::from_slice(&bytes[m..r]).unwrap();
-            blocks[i] = EncodeBlock { s: [s0, s1] };
-        }
+        // split into encode blocks,
+        // packing the halves of every block into bignum integers
+        let blocks = bytes
+            .chunks_exact(BLOCK_SIZE)
+            .map(|b| {
+                let (s0, s1) = b.split_at(BLOCK_HALF_SIZE);
+                EncodeBlock {
+                    s: [
+                        BigNum::from_slice(s0).unwrap(),
+                        BigNum::from_slice(s1).unwrap(),
+                    ],
+                }
+            })
+            .collect();
 
         EncodedShard {
             blocks,
             idx: self.idx,
+            prf: self.prf,
+            replica,
+            key: None,
         }
     }
 }
 
 impl EncodedShard {
-    pub fn unpack(&self) -> Shard {
-        let mut bytes = Vec::with_capacity(SHARD_SIZE);
+    /// Returns the index of the shard in the codeword
+    pub fn index(&self) -> u16 {
+        self.idx
+    }
+
+    /// Returns the PRF used by the encoding
+    pub fn prf(&self) -> PrfKind {
+        self.prf
+    }
+
+    /// Returns the size of the shard (once decoded) in bytes
+    pub fn size(&self) -> usize {
+        self.blocks.len() * BLOCK_SIZE
+    }
+
+    /// Returns the replica number used when encoding
+    pub fn replica(&self) -> u32 {
+        self.replica
+    }
+
+    /// Returns the fingerprint of the key used to encode the shard (if recorded)
+    pub fn fingerprint(&self) -> Option<&Fingerprint> {
+        self.key.as_ref()
+    }
+
+    /// Sets or removes the key fingerprint included in the serialized shard
+    pub fn set_fingerprint(&mut self, key: Option<Fingerprint>) {
+        self.key = key;
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut ser = Vec::with_capacity(
+            2 + 4 + 1 + 1 + Fingerprint::SIZE + self.blocks.len() * 2 * MODULUS_BYTES,
+        );
+        ser.extend(&self.idx.to_be_bytes());
+        ser.extend(&self.replica.to_be_bytes());
+        ser.push(self.prf.id());
+        write_optional_fingerprint(&mut ser, &self.key);
+        for block in self.blocks.iter() {
+            for s in block.s.iter() {
+                ser.extend(s.to_vec_padded(MODULUS_BYTES).unwrap());
+            }
+        }
+        ser
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let mut rd = Reader::new(bytes);
+        let idx = rd.u16()?;
+        let replica = rd.u32()?;
+        let prf = PrfKind::from_id(rd.u8()?)?;
+        let key = read_optional_fingerprint(&mut rd)?;
+
+        // the number of blocks is implied by the length
+        let left = rd.remaining();
+        if left == 0 || !left.is_multiple_of(2 * MODULUS_BYTES) {
+            return Err(Error::Malformed);
+        }
+        let count = left / (2 * MODULUS_BYTES);
+        let mut blocks = Vec::with_capacity(count);
+        for _ in 0..count {
+            let s0 = BigNum::from_slice(rd.take(MODULUS_BYTES)?)?;
+            let s1 = BigNum::from_slice(rd.take(MODULUS_BYTES)?)?;
+            blocks.push(EncodeBlock { s: [s0, s1] });
+        }
+        rd.finish()?;
+        Ok(EncodedShard {
+            idx,
+            replica,
+            prf,
+            key,
+            blocks,
+        })
+    }
+
+    /// Unpacks a decoded shard,
+    /// fails with Error::OutOfRange if a block does not fit in the shard
+    /// (the shard was forged, corrupted or encoded under a different key)
+    pub fn unpack(&self) -> Result<Shard, Error> {
+        let mut bytes = Vec::with_capacity(self.blocks.len() * BLOCK_SIZE);
         let mut push = |n: &BigNum| {
             let bs = n.to_vec();
-            for _ in bs.len()..BLOCK_HALF_SIZE {
-                bytes.push(0x0);
+            if bs.len() > BLOCK_HALF_SIZE {
+                return Err(Error::OutOfRange);
             }
+            bytes.resize(bytes.len() + BLOCK_HALF_SIZE - bs.len(), 0x0);
             bytes.extend(&bs[..]);
-            debug_assert_eq!(bytes.len() % BLOCK_HALF_SIZE, 0);
+            Ok(())
         };
 
         // unpack bignum integer to bytes
-        for i in 0..SHARD_BLOCKS {
-            push(&self.blocks[i].s[0]);
-            push(&self.blocks[i].s[1]);
+        for block in self.blocks.iter() {
+            push(&block.s[0])?;
+            push(&block.s[1])?;
         }
-        debug_assert_eq!(bytes.len() % BLOCK_SIZE, 0);
-        debug_assert_eq!(bytes.len(), SHARD_SIZE);
+        debug_assert_eq!(bytes.len(), self.blocks.len() * BLOCK_SIZE);
 
         // pack bytes into GF(2^16) elements
-        Shard::new(self.idx, &bytes)
+        Ok(Shard::new(self.idx, self.prf, &bytes))
     }
 }
 
@@ -169,8 +565,9 @@ impl fmt::Debug for Shard {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Shard {{ idx: {}, coords: {:?} }}",
+            "Shard {{ idx: {}, prf: {:?}, coords: {:?} }}",
             self.idx,
+            self.prf,
             &self.coords[..]
         )
     }
@@ -178,8 +575,8 @@ impl fmt::Debug for Shard {
 
 impl Clone for EncodeBlock {
     fn clone(&self) -> Self {
-        let s0 = self.s[0].to_owned().unwrap();
-        let s1 = self.s[1].to_owned().unwrap();
-        EncodeBlock { s: [s0, s1] }
+        EncodeBlock {
+            s: [self.s[0].clone(), self.s[1].clone()],
+        }
     }
 }
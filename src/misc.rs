@@ -1,23 +1,69 @@
-use openssl::sha::Sha256;
-use openssl::symm::{encrypt, Cipher};
+use super::Error;
 
-/* Expand
- *
+/* Reader
  *
+ * Cursor for parsing serialized objects,
+ * running past the end is reported as a malformed serialization.
  */
-pub fn expand(tweak: &[u8], value: &[u8], size: usize) -> Vec<u8> {
-    // hash the input
-    let mut hsh = Sha256::new();
-    hsh.update(tweak);
-    hsh.update(value);
-    let key = hsh.finish();
-
-    // allocate result buffer
-    let mut zero: Vec<u8> = Vec::with_capacity(size);
-    zero.resize(size, 0);
-
-    // stretch into a large random number using AES-CTR
-    let iv = [0u8; 16];
-    let cipher = Cipher::aes_256_ctr();
-    encrypt(cipher, &key[..], Some(&iv[..]), &zero[..]).unwrap()
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if n > self.bytes.len() {
+            return Err(Error::Malformed);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Error> {
+        let mut v = [0u8; 2];
+        v.copy_from_slice(self.take(2)?);
+        Ok(u16::from_be_bytes(v))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        let mut v = [0u8; 4];
+        v.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(v))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        let mut v = [0u8; 8];
+        v.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(v))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, Error> {
+        let v = self.u64()?;
+        if v > usize::MAX as u64 {
+            return Err(Error::Malformed);
+        }
+        Ok(v as usize)
+    }
+
+    // number of bytes left
+    pub fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    // check that the entire input was consumed
+    pub fn finish(self) -> Result<(), Error> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Malformed)
+        }
+    }
 }
@@ -0,0 +1,101 @@
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+
+use super::primitives::{aes_256_ctr, Sha256};
+use super::Error;
+
+/* Pseudo-random function with variable output length,
+ * used in every Feistel round of the full-domain hashing (File)
+ * and the encoding permutation (EncodeBlock).
+ */
+pub trait Prf {
+    /// Fills out with pseudo-random bytes determined by (tweak, value)
+    fn expand(&self, tweak: &[u8], value: &[u8], out: &mut [u8]);
+}
+
+/// SHA-256(tweak || value) used as key for AES-256-CTR (the original construction)
+pub struct Sha256AesCtr;
+
+/// BLAKE3 in key derivation mode, output stretched using the XOF
+pub struct Blake3Xof;
+
+/// SHAKE256
+pub struct Shake256;
+
+// domain separation for the constructions without a legacy format
+const PRF_CONTEXT: &str = "portage prf v1";
+
+impl Prf for Sha256AesCtr {
+    fn expand(&self, tweak: &[u8], value: &[u8], out: &mut [u8]) {
+        // hash the input
+        let mut hsh = Sha256::new();
+        hsh.update(tweak);
+        hsh.update(value);
+        let key = hsh.finish();
+
+        // stretch into a large random number using AES-CTR
+        aes_256_ctr(&key, out);
+    }
+}
+
+impl Prf for Blake3Xof {
+    fn expand(&self, tweak: &[u8], value: &[u8], out: &mut [u8]) {
+        let mut hsh = blake3::Hasher::new_derive_key(PRF_CONTEXT);
+        hsh.update(&[tweak.len() as u8]);
+        hsh.update(tweak);
+        hsh.update(value);
+        hsh.finalize_xof().fill(out);
+    }
+}
+
+impl Prf for Shake256 {
+    fn expand(&self, tweak: &[u8], value: &[u8], out: &mut [u8]) {
+        let mut hsh = sha3::Shake256::default();
+        hsh.update(PRF_CONTEXT.as_bytes());
+        hsh.update(&[tweak.len() as u8]);
+        hsh.update(tweak);
+        hsh.update(value);
+        hsh.finalize_xof().read(out);
+    }
+}
+
+/// Selects the PRF, part of the parameter set recorded in the header
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PrfKind {
+    #[default]
+    Sha256AesCtr,
+    Blake3,
+    Shake256,
+}
+
+impl PrfKind {
+    pub fn prf(self) -> &'static dyn Prf {
+        match self {
+            PrfKind::Sha256AesCtr => &Sha256AesCtr,
+            PrfKind::Blake3 => &Blake3Xof,
+            PrfKind::Shake256 => &Shake256,
+        }
+    }
+
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            PrfKind::Sha256AesCtr => 0,
+            PrfKind::Blake3 => 1,
+            PrfKind::Shake256 => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Result<Self, Error> {
+        match id {
+            0 => Ok(PrfKind::Sha256AesCtr),
+            1 => Ok(PrfKind::Blake3),
+            2 => Ok(PrfKind::Shake256),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    pub(crate) fn expand(self, tweak: &[u8], value: &[u8], size: usize) -> Vec<u8> {
+        let mut out = vec![0u8; size];
+        self.prf().expand(tweak, value, &mut out[..]);
+        out
+    }
+}
@@ -0,0 +1,23 @@
+/* Symmetric primitives backend
+ *
+ * Hashing, the AES constructions, the password KDF and the system RNG
+ * go through the functions exported here, so that the crate builds without
+ * a system OpenSSL. Both backends must produce bit-identical results:
+ *
+ * - ossl:   OpenSSL (feature "openssl", default)
+ * - native: pure-Rust RustCrypto crates (feature "rust-crypto")
+ *
+ * AES-256-CTR starts from an all-zero IV with a 128-bit big-endian counter.
+ */
+
+#[cfg(feature = "rust-crypto")]
+mod native;
+
+#[cfg(not(feature = "rust-crypto"))]
+mod ossl;
+
+#[cfg(feature = "rust-crypto")]
+pub use native::*;
+
+#[cfg(not(feature = "rust-crypto"))]
+pub use ossl::*;
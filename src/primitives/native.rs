@@ -0,0 +1,100 @@
+use std::io;
+
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce, Tag};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Digest;
+
+use super::super::Error;
+
+type Aes256Ctr = ctr::Ctr128BE<aes::Aes256>;
+
+// same interface as openssl::sha::Sha256
+pub struct Sha256(sha2::Sha256);
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Sha256(sha2::Sha256::new())
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data)
+    }
+
+    pub fn finish(self) -> [u8; 32] {
+        self.0.finalize().into()
+    }
+}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    sha2::Sha256::digest(data).into()
+}
+
+// overwrites out with the AES-256-CTR keystream (zero IV)
+pub fn aes_256_ctr(key: &[u8; 32], out: &mut [u8]) {
+    let iv = [0u8; 16];
+    out.fill(0);
+    Aes256Ctr::new(key.into(), &iv.into()).apply_keystream(out);
+}
+
+pub fn aes_256_gcm_seal(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    data: &[u8],
+    tag: &mut [u8],
+) -> Result<Vec<u8>, Error> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| Error::Malformed)?;
+    let mut ct = data.to_vec();
+    // only fails beyond the input limit of GCM (64 GiB)
+    let t = cipher
+        .encrypt_in_place_detached(Nonce::from_slice(nonce), aad, &mut ct[..])
+        .map_err(|_| Error::Malformed)?;
+    tag.copy_from_slice(&t[..]);
+    Ok(ct)
+}
+
+// fails with Error::Decrypt unless the tag is valid
+pub fn aes_256_gcm_open(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    data: &[u8],
+    tag: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| Error::Malformed)?;
+    if nonce.len() != 12 || tag.len() != 16 {
+        return Err(Error::Decrypt);
+    }
+    let mut pt = data.to_vec();
+    cipher
+        .decrypt_in_place_detached(
+            Nonce::from_slice(nonce),
+            aad,
+            &mut pt[..],
+            Tag::from_slice(tag),
+        )
+        .map_err(|_| Error::Decrypt)?;
+    Ok(pt)
+}
+
+// the memory used is bounded by the caller (see keyfile::derive)
+pub fn scrypt(
+    password: &[u8],
+    salt: &[u8],
+    log_n: u8,
+    r: u32,
+    p: u32,
+    out: &mut [u8],
+) -> Result<(), Error> {
+    let params = scrypt::Params::new(log_n, r, p, out.len()).map_err(|_| Error::Malformed)?;
+    scrypt::scrypt(password, salt, &params, out).map_err(|_| Error::Malformed)
+}
+
+pub fn rand_bytes(buf: &mut [u8]) -> Result<(), Error> {
+    OsRng
+        .try_fill_bytes(buf)
+        .map_err(|e| Error::Io(io::Error::from(e)))
+}
@@ -0,0 +1,92 @@
+use std::cell::RefCell;
+
+use openssl::cipher::Cipher;
+use openssl::cipher_ctx::CipherCtx;
+use openssl::symm::{self, decrypt_aead, encrypt_aead};
+
+pub use openssl::sha::{sha256, Sha256};
+
+use super::super::Error;
+
+thread_local! {
+    // AES-256-CTR context, re-keyed on every call rather than reallocated
+    static AES_CTR: RefCell<CipherCtx> = RefCell::new({
+        let mut ctx = CipherCtx::new().unwrap();
+        ctx.encrypt_init(Some(Cipher::aes_256_ctr()), None, None)
+            .unwrap();
+        ctx
+    });
+}
+
+// overwrites out with the AES-256-CTR keystream (zero IV)
+pub fn aes_256_ctr(key: &[u8; 32], out: &mut [u8]) {
+    let iv = [0u8; 16];
+    out.fill(0);
+    AES_CTR.with(|ctx| {
+        let mut ctx = ctx.borrow_mut();
+        ctx.encrypt_init(None, Some(&key[..]), Some(&iv[..]))
+            .unwrap();
+        let n = ctx.cipher_update_inplace(out, out.len()).unwrap();
+        debug_assert_eq!(n, out.len());
+    });
+}
+
+pub fn aes_256_gcm_seal(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    data: &[u8],
+    tag: &mut [u8],
+) -> Result<Vec<u8>, Error> {
+    Ok(encrypt_aead(
+        symm::Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        aad,
+        data,
+        tag,
+    )?)
+}
+
+// fails with Error::Decrypt unless the tag is valid
+pub fn aes_256_gcm_open(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    data: &[u8],
+    tag: &[u8],
+) -> Result<Vec<u8>, Error> {
+    decrypt_aead(
+        symm::Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        aad,
+        data,
+        tag,
+    )
+    .map_err(|_| Error::Decrypt)
+}
+
+// the memory used is bounded by the caller (see keyfile::derive)
+pub fn scrypt(
+    password: &[u8],
+    salt: &[u8],
+    log_n: u8,
+    r: u32,
+    p: u32,
+    out: &mut [u8],
+) -> Result<(), Error> {
+    Ok(openssl::pkcs5::scrypt(
+        password,
+        salt,
+        1 << log_n,
+        r as u64,
+        p as u64,
+        u64::MAX,
+        out,
+    )?)
+}
+
+pub fn rand_bytes(buf: &mut [u8]) -> Result<(), Error> {
+    Ok(openssl::rand::rand_bytes(buf)?)
+}
@@ -1,10 +1,10 @@
 use reed_solomon_erasure::galois_16::Field;
 use reed_solomon_erasure::ReedSolomon;
 
+use std::collections::BTreeMap;
 use std::iter::FromIterator;
 
-use super::SHARD_ELEMS;
-use super::{File, Header, Shard};
+use super::{Error, File, Header, Shard, MAX_SHARDS};
 
 impl AsRef<[[u8; 2]]> for Shard {
     fn as_ref(&self) -> &[[u8; 2]] {
@@ -23,33 +23,29 @@ impl FromIterator<[u8; 2]> for Shard {
     where
         T: IntoIterator<Item = [u8; 2]>,
     {
-        let mut iter = iter.into_iter();
-        let mut shard = Shard {
+        Shard {
             idx: 0,
-            coords: [[0u8; 2]; SHARD_ELEMS],
-        };
-
-        for i in 0..SHARD_ELEMS {
-            if let Some(coord) = iter.next() {
-                shard.coords[i] = coord
-            }
+            prf: Default::default(),
+            coords: iter.into_iter().collect(),
         }
-
-        shard
     }
 }
 
 impl File {
-    pub fn reconstruct(header: &Header, shards: &[Shard]) -> Result<Self, ()> {
+    pub fn reconstruct(header: &Header, shards: &[Shard]) -> Result<Self, Error> {
         // check that sufficient data to reconstruct
         let dimension = header.shards();
         if dimension > shards.len() {
-            return Err(());
+            return Err(Error::Reconstruct);
         }
 
         // obtain maximum shard index
+        // (and check that every shard has the size recorded in the header)
         let mut max: usize = 0;
         for s in shards {
+            if s.coords.len() * 2 != header.shard_size {
+                return Err(Error::Reconstruct);
+            }
             let idx = s.idx as usize;
             if idx > max {
                 max = idx;
@@ -64,31 +60,56 @@ impl File {
             sparse[s.idx as usize] = Some(s.clone());
         }
 
-        // create RS instance
-        let rs: ReedSolomon<Field> = ReedSolomon::new(dimension, (max + 1) - dimension).unwrap();
-
         // reconstruct data shards
-        rs.reconstruct_data(&mut sparse).map_err(|_| ())?;
+        // (if only data shards are present, they are all present)
+        if dimension > 0 && max + 1 > dimension {
+            let rs: ReedSolomon<Field> =
+                ReedSolomon::new(dimension, (max + 1) - dimension).unwrap();
+            rs.reconstruct_data(&mut sparse)
+                .map_err(|_| Error::Reconstruct)?;
+        }
 
         // pack into file
         let mut shards: Vec<Shard> = Vec::with_capacity(dimension);
-        for i in 0..dimension {
-            let mut coord = sparse[i].unwrap();
+        for (i, coord) in sparse.into_iter().take(dimension).enumerate() {
+            let mut coord = coord.unwrap();
             coord.idx = i as u16;
+            coord.prf = header.prf;
             shards.push(coord);
         }
         Ok(File {
             length: header.length,
+            prf: header.prf,
+            shard_size: header.shard_size,
+            segment: header.segment,
+            seal: header.seal,
+            compression: header.compression,
+            original: header.original,
+            digest: header.digest,
             shards,
         })
     }
 
-    pub fn shards(&self, expansion: usize) -> (Header, Vec<Shard>) {
+    /// Splits the file into shards, extended with expansion parity shards,
+    /// fails with Error::TooManyShards if there would be more than MAX_SHARDS
+    pub fn shards(&self, expansion: usize) -> Result<(Header, Vec<Shard>), Error> {
         // create header
         let header = Header {
             length: self.length,
+            prf: self.prf,
+            shard_size: self.shard_size,
+            segment: self.segment,
+            seal: self.seal,
+            compression: self.compression,
+            original: self.original,
+            digest: self.digest,
+            key: None,
+            manifest: BTreeMap::new(),
         };
         let dimension = header.shards();
+        if dimension.saturating_add(expansion) > MAX_SHARDS {
+            return Err(Error::TooManyShards);
+        }
 
         // create codeword buffer
         let code_len = dimension + expansion;
@@ -99,16 +120,17 @@ impl File {
             } else {
                 code.push(Shard {
                     idx: i as u16,
-                    coords: [Default::default(); SHARD_ELEMS],
+                    prf: self.prf,
+                    coords: vec![Default::default(); self.shard_size / 2],
                 });
             }
         }
 
         // use RS coding to extend and create new shards
-        if expansion > 0 {
+        if expansion > 0 && dimension > 0 {
             let rs: ReedSolomon<Field> = ReedSolomon::new(dimension, expansion).unwrap();
             rs.encode(&mut code).unwrap();
         }
-        (header, code)
+        Ok((header, code))
     }
 }
@@ -1,52 +1,367 @@
 use std::cmp::Ordering;
 use std::mem;
+use std::slice;
 
-use openssl::bn::{BigNum, BigNumContext, BigNumContextRef};
-use openssl::error::ErrorStack;
+use zeroize::Zeroizing;
 
-use super::misc::expand;
-use super::PRIME_SIZE;
-use super::{EncodeBlock, EncodedShard};
+use super::bn::{BigNum, BigNumContext, MontContext};
+use super::{EncodeBlock, EncodedShard, Error, File, Fingerprint, Header, PrfKind, Shard};
+use super::{MODULUS_BYTES, PRIME_SIZE};
 
 /* e = 3 is fixed */
 pub struct EncodingKey {
-    ctx: BigNumContext,
+    ws: Workspace,
     n: BigNum,
     d: BigNum,
+    mont: MontContext,
+    crt: Trapdoor,
 }
 
 // impl ordering
 pub struct DecodingKey {
-    ctx: BigNumContext,
+    ws: Workspace,
     n: BigNum,
+    mont: MontContext,
 }
 
 impl Clone for EncodingKey {
     fn clone(&self) -> Self {
-        Self {
-            ctx: BigNumContext::new().unwrap(),
-            n: self.n.to_owned().unwrap(),
-            d: self.d.to_owned().unwrap(),
-        }
+        Self::from_parts(
+            self.n.clone(),
+            self.d.clone(),
+            self.crt.p.clone(),
+            self.crt.q.clone(),
+        )
+        .unwrap()
+    }
+}
+
+// wipe the trapdoor (BN_clear_free semantics)
+impl Drop for EncodingKey {
+    fn drop(&mut self) {
+        self.d.clear();
     }
 }
 
 impl Clone for DecodingKey {
     fn clone(&self) -> Self {
-        Self {
-            ctx: BigNumContext::new().unwrap(),
-            n: self.n.to_owned().unwrap(),
+        Self::from_modulus(self.n.clone()).unwrap()
+    }
+}
+
+/* Trapdoor in CRT form:
+ * v^d mod n is assembled from v^dp mod p and v^dq mod q,
+ * with the Montgomery setup for both primes computed once.
+ */
+struct Trapdoor {
+    p: BigNum,
+    q: BigNum,
+    dp: BigNum,   // d mod (p - 1)
+    dq: BigNum,   // d mod (q - 1)
+    qinv: BigNum, // q^-1 mod p (Montgomery form)
+    mont_p: MontContext,
+    mont_q: MontContext,
+}
+
+impl Drop for Trapdoor {
+    fn drop(&mut self) {
+        self.p.clear();
+        self.q.clear();
+        self.dp.clear();
+        self.dq.clear();
+        self.qinv.clear();
+    }
+}
+
+impl Trapdoor {
+    fn new(
+        ctx: &mut BigNumContext,
+        d: &BigNum,
+        mut p: BigNum,
+        mut q: BigNum,
+    ) -> Result<Self, Error> {
+        let mont = MontContext::new(&p, ctx).and_then(|mp| Ok((mp, MontContext::new(&q, ctx)?)));
+        let (mont_p, mont_q) = match mont {
+            Ok(mont) => mont,
+            Err(err) => {
+                p.clear();
+                q.clear();
+                return Err(err);
+            }
+        };
+
+        // the parts are wiped on drop, also if the precomputation fails
+        let mut crt = Trapdoor {
+            p,
+            q,
+            dp: BigNum::new()?,
+            dq: BigNum::new()?,
+            qinv: BigNum::new()?,
+            mont_p,
+            mont_q,
+        };
+        let mut tmp = BigNum::new()?;
+        let res = crt.precompute(ctx, d, &mut tmp);
+        tmp.clear();
+        res.map(|_| crt)
+    }
+
+    fn precompute(
+        &mut self,
+        ctx: &mut BigNumContext,
+        d: &BigNum,
+        tmp: &mut BigNum,
+    ) -> Result<(), Error> {
+        // dp = d mod (p - 1)
+        tmp.copy_from(&self.p)?;
+        tmp.sub_word(1)?;
+        self.dp.nnmod(d, tmp, ctx)?;
+
+        // dq = d mod (q - 1)
+        tmp.copy_from(&self.q)?;
+        tmp.sub_word(1)?;
+        self.dq.nnmod(d, tmp, ctx)?;
+
+        // the exponents are secret
+        self.dp.set_const_time();
+        self.dq.set_const_time();
+
+        // q^-1 mod p
+        tmp.mod_inverse(&self.q, &self.p, ctx)?;
+        self.qinv.mont_form(tmp, &self.mont_p, ctx)
+    }
+
+    /* Recovers the factorization from the private exponent (e = 3):
+     * 3d - 1 = k (p - 1)(q - 1) with k in {1, 2} since d < (p - 1)(q - 1),
+     * which gives p + q, after which p, q are the roots of X^2 - (p + q) X + n.
+     */
+    fn recover(ctx: &mut BigNumContext, n: &BigNum, d: &BigNum) -> Result<Self, Error> {
+        let three = BigNum::from_u32(3)?;
+        let four = BigNum::from_u32(4)?;
+        let mut ed = BigNum::new()?;
+        let mut phi = BigNum::new()?;
+        let mut s = BigNum::new()?;
+        let mut disc = BigNum::new()?;
+        let mut root = BigNum::new()?;
+        let mut tmp = BigNum::new()?;
+        let mut p = BigNum::new()?;
+        let mut q = BigNum::new()?;
+
+        // tries a candidate for (p - 1)(q - 1)
+        let mut factor = |ctx: &mut BigNumContext, phi: &BigNum| -> Result<bool, Error> {
+            // s = p + q = n - phi + 1
+            if s.checked_sub(n, phi).is_err() {
+                return Ok(false);
+            }
+            s.add_word(1)?;
+
+            // disc = s^2 - 4n = (p - q)^2
+            tmp.checked_mul(&s, &s, ctx)?;
+            root.checked_mul(n, &four, ctx)?;
+            if disc.checked_sub(&tmp, &root).is_err() {
+                return Ok(false);
+            }
+            root.sqrt(&disc, ctx)?;
+
+            // p, q = (s +- root) / 2
+            tmp.checked_add(&s, &root)?;
+            p.rshift1(&tmp)?;
+            tmp.checked_sub(&s, &root)?;
+            q.rshift1(&tmp)?;
+            tmp.checked_mul(&p, &q, ctx)?;
+            Ok(tmp.ucmp(n) == Ordering::Equal && q.ucmp(&three) != Ordering::Less)
+        };
+
+        // 3d - 1
+        let found = ed.checked_mul(&three, d, ctx).and_then(|_| {
+            ed.sub_word(1)?;
+            if factor(ctx, &ed)? {
+                return Ok(true);
+            }
+            phi.rshift1(&ed)?;
+            factor(ctx, &phi)
+        });
+
+        for v in [&mut ed, &mut phi, &mut s, &mut disc, &mut root, &mut tmp] {
+            v.clear();
+        }
+        match found {
+            Ok(true) => Trapdoor::new(ctx, d, p, q),
+            found => {
+                p.clear();
+                q.clear();
+                found.and(Err(Error::Malformed))
+            }
         }
     }
+
+    // res = v^d mod n (m1, m2 are scratch)
+    fn apply(
+        &self,
+        ctx: &mut BigNumContext,
+        res: &mut BigNum,
+        m1: &mut BigNum,
+        m2: &mut BigNum,
+        v: &BigNum,
+    ) -> Result<(), Error> {
+        // m1 = v^dp mod p
+        res.nnmod(v, &self.p, ctx)?;
+        m1.mod_exp_mont(res, &self.dp, &self.mont_p, ctx)?;
+
+        // m2 = v^dq mod q
+        res.nnmod(v, &self.q, ctx)?;
+        m2.mod_exp_mont(res, &self.dq, &self.mont_q, ctx)?;
+
+        // h = (m1 - m2) q^-1 mod p
+        res.mod_sub(m1, m2, &self.p, ctx)?;
+        m1.mont_mul(res, &self.qinv, &self.mont_p, ctx)?;
+
+        // res = m2 + h q
+        res.checked_mul(m1, &self.q, ctx)?;
+        m1.checked_add(res, m2)?;
+        mem::swap(res, m1);
+        Ok(())
+    }
+}
+
+/* Serialized position of a block: shard index || block index || replica,
+ * where the replica is omitted for replica 0,
+ * such that the original replica matches shards encoded before replicas were added.
+ */
+#[derive(Copy, Clone)]
+struct BlockTweak {
+    bytes: [u8; mem::size_of::<u16>() + 2 * mem::size_of::<u32>()],
+    len: usize,
+}
+
+impl BlockTweak {
+    fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
 }
 
-fn block_to_tweak(shard_idx: u16, block_idx: u32) -> [u8; 6] {
-    let mut tweak = [0u8; mem::size_of::<u16>() + mem::size_of::<u32>()];
-    tweak[0..2].copy_from_slice(&shard_idx.to_le_bytes()[..]);
-    tweak[2..6].copy_from_slice(&block_idx.to_le_bytes()[..]);
+fn block_to_tweak(shard_idx: u16, replica: u32, block_idx: u32) -> BlockTweak {
+    let mut tweak = BlockTweak {
+        bytes: Default::default(),
+        len: 6,
+    };
+    tweak.bytes[0..2].copy_from_slice(&shard_idx.to_le_bytes()[..]);
+    tweak.bytes[2..6].copy_from_slice(&block_idx.to_le_bytes()[..]);
+    if replica != 0 {
+        tweak.bytes[6..10].copy_from_slice(&replica.to_le_bytes()[..]);
+        tweak.len = 10;
+    }
     tweak
 }
 
+// block of a shard awaiting encoding in a batch
+struct Pending<'a> {
+    block: &'a mut EncodeBlock,
+    prf: PrfKind,
+    tweak: BlockTweak,
+}
+
+/* Workspace
+ *
+ * Scratch space reused across blocks and Feistel rounds,
+ * so that encoding / decoding a block performs no heap allocation
+ * once the buffers have grown to their working size.
+ */
+pub(crate) struct Workspace {
+    ctx: BigNumContext,
+    p: BigNum,           // output of the round function
+    inv: BigNum,         // inverse of the round function output
+    new: BigNum,         // next value of a half
+    tmp: BigNum,         // intermediate values
+    value: Vec<u8>,      // serialized input to the PRF
+    random: Vec<u8>,     // output of the PRF
+    tweak: Vec<u8>,      // round || block tweak
+    batch: Vec<BigNum>,  // round function outputs / their inverses (encode_batch)
+    prefix: Vec<BigNum>, // prefix products for simultaneous inversion
+}
+
+impl Workspace {
+    pub(crate) fn new() -> Result<Self, Error> {
+        Ok(Workspace {
+            ctx: BigNumContext::new()?,
+            p: BigNum::new()?,
+            inv: BigNum::new()?,
+            new: BigNum::new()?,
+            tmp: BigNum::new()?,
+            value: Vec::with_capacity(MODULUS_BYTES),
+            random: Vec::with_capacity(MODULUS_BYTES + 16),
+            tweak: Vec::with_capacity(16),
+            batch: Vec::new(),
+            prefix: Vec::new(),
+        })
+    }
+
+    // grows the batch buffers to hold (at least) k values
+    fn reserve_batch(&mut self, k: usize) -> Result<(), Error> {
+        while self.batch.len() < k {
+            self.batch.push(BigNum::new()?);
+            self.prefix.push(BigNum::new()?);
+        }
+        Ok(())
+    }
+
+    /* Montgomery's simultaneous inversion:
+     * replaces batch[..k] by their inverses mod n
+     * using a single modular inversion and 3(k - 1) + 2 multiplications.
+     *
+     * The values and their inverses are in Montgomery form,
+     * which is preserved by Montgomery multiplication.
+     */
+    fn invert_batch(&mut self, k: usize, n: &BigNum, mont: &MontContext) -> Result<(), Error> {
+        if k == 0 {
+            return Ok(());
+        }
+
+        // prefix[i] = batch[0] * ... * batch[i]
+        self.prefix[0].copy_from(&self.batch[0])?;
+        for i in 1..k {
+            let (done, rest) = self.prefix.split_at_mut(i);
+            rest[0].mont_mul(&done[i - 1], &self.batch[i], mont, &mut self.ctx)?;
+        }
+
+        // inv = (batch[0] * ... * batch[k - 1])^-1
+        // (the inverse of x R is x^-1 R^-1, converted twice to get x^-1 R)
+        self.tmp
+            .mod_inverse(&self.prefix[k - 1], n, &mut self.ctx)?;
+        self.new.mont_form(&self.tmp, mont, &mut self.ctx)?;
+        self.inv.mont_form(&self.new, mont, &mut self.ctx)?;
+
+        // peel off one element at a time
+        for i in (1..k).rev() {
+            // batch[i]^-1 = inv * prefix[i - 1]
+            self.tmp
+                .mont_mul(&self.inv, &self.prefix[i - 1], mont, &mut self.ctx)?;
+
+            // inv = (batch[0] * ... * batch[i - 1])^-1
+            self.new
+                .mont_mul(&self.inv, &self.batch[i], mont, &mut self.ctx)?;
+            mem::swap(&mut self.inv, &mut self.new);
+            mem::swap(&mut self.batch[i], &mut self.tmp);
+        }
+        mem::swap(&mut self.batch[0], &mut self.inv);
+        Ok(())
+    }
+
+    // F function: p = PRF(tweak, v) mod n
+    fn f(&mut self, prf: PrfKind, v: &BigNum, n: &BigNum) -> Result<(), Error> {
+        // stretch into a large random string
+        let len = n.num_bytes() + 16;
+        v.write_to(&mut self.value);
+        self.random.resize(len, 0);
+        prf.prf()
+            .expand(&self.tweak[..], &self.value[..], &mut self.random[..]);
+
+        // reduce mod n
+        self.tmp.copy_from_slice(&self.random[..])?;
+        self.p.nnmod(&self.tmp, n, &mut self.ctx)
+    }
+}
+
 impl EncodeBlock {
     // rounds of encoding (trapdoor application)
     const ROUNDS: u32 = 2;
@@ -54,42 +369,38 @@ impl EncodeBlock {
     // rounds of feistel cipher / full-domain hashing
     const FEISTEL: u32 = 8;
 
-    // feistel based permutation
+    // moves the new value of the right half into place,
+    // swapping the halves in all but the last Feistel round
+    // (the displaced half becomes the next scratch value)
+    #[inline(always)]
+    fn shift(&mut self, new: &mut BigNum, r: u32) {
+        if r < Self::FEISTEL - 1 {
+            // swap(b[0], b[1])
+            mem::swap(&mut self.s[0], new);
+            mem::swap(&mut self.s[1], new);
+        } else {
+            // dont swap at last round
+            mem::swap(&mut self.s[1], new);
+        }
+    }
+
+    // inverse of the feistel based permutation
+    // (the forward direction is computed by encode_batch)
     #[inline(always)]
-    fn feistel(
+    fn feistel_inv(
         &mut self,
-        ctx: &mut BigNumContextRef,
-        reverse: bool,
+        ws: &mut Workspace,
+        prf: PrfKind,
         n: &BigNum, // modulus
+        mont: &MontContext,
         twk: &[u8],
-    ) -> Result<(), ErrorStack> {
-        // F function
-        fn f(
-            ctx: &mut BigNumContextRef,
-            res: &mut BigNum,
-            tweak: &[u8],
-            v: &BigNum,
-            n: &BigNum,
-        ) -> Result<(), ErrorStack> {
-            // stretch into a large random string
-            let len = (n.num_bytes() + 16) as usize;
-            let random = expand(tweak, &v.to_vec(), len);
-
-            // reduce mod n
-            let random = BigNum::from_slice(&random[..])?;
-            res.nnmod(&random, n, ctx)
-        }
-
-        // halfs
-        let mut tmp1 = BigNum::new()?;
-        let mut tmp2 = BigNum::new()?;
-
+    ) -> Result<(), Error> {
         // copy over tweak
-        let mut tweak: Vec<u8> = vec![0; twk.len() + 1];
-        tweak[1..].copy_from_slice(twk);
+        ws.tweak.clear();
+        ws.tweak.push(0);
+        ws.tweak.extend_from_slice(twk);
 
-        debug_assert_eq!(tweak[0], 0);
-        debug_assert_eq!(tweak.len(), twk.len() + 1);
+        debug_assert_eq!(ws.tweak.len(), twk.len() + 1);
         debug_assert_eq!(self.s[0].ucmp(n), Ordering::Less);
         debug_assert_eq!(self.s[1].ucmp(n), Ordering::Less);
 
@@ -97,32 +408,16 @@ impl EncodeBlock {
             debug_assert!(r < 256);
 
             // add round number to tweak
-            debug_assert_eq!(&tweak[1..], twk);
-            tweak[0] = if reverse { Self::FEISTEL - 1 - r } else { r } as u8;
+            debug_assert_eq!(&ws.tweak[1..], twk);
+            ws.tweak[0] = (Self::FEISTEL - 1 - r) as u8;
 
             // P = F(b[0])
-            f(ctx, &mut tmp1, &tweak[..], &self.s[0], &n)?;
-
-            // mix with right half
-            let mut new = BigNum::new()?;
-            if reverse {
-                // b[1] *= P
-                new.mod_mul(&&self.s[1], &tmp1, &n, ctx)?;
-            } else {
-                // b[1] *= P^-1
-                tmp2.mod_inverse(&tmp1, &n, ctx)?;
-                new.mod_mul(&&self.s[1], &tmp2, &n, ctx)?;
-            }
+            ws.f(prf, &self.s[0], n)?;
 
-            // swap
-            if r < Self::FEISTEL - 1 {
-                // swap(b[0], b[1])
-                mem::swap(&mut self.s[0], &mut new);
-                mem::swap(&mut self.s[1], &mut new);
-            } else {
-                // dont swap at last round
-                mem::swap(&mut self.s[1], &mut new);
-            }
+            // b[1] *= P
+            ws.tmp.mont_form(&ws.p, mont, &mut ws.ctx)?;
+            ws.new.mont_mul(&self.s[1], &ws.tmp, mont, &mut ws.ctx)?;
+            self.shift(&mut ws.new, r);
         }
         Ok(())
     }
@@ -130,115 +425,156 @@ impl EncodeBlock {
     // 1 round of decoding
     fn round_inv(
         &mut self,
-        ctx: &mut BigNumContextRef,
-        n: &BigNum,   // modulus
-        tweak: &[u8], // tweak for "invertable RO"
-    ) -> Result<(), ErrorStack> {
+        ws: &mut Workspace,
+        prf: PrfKind,       // PRF of the "invertable RO"
+        n: &BigNum,         // modulus
+        mont: &MontContext, // Montgomery setup for n
+        tweak: &[u8],       // tweak for "invertable RO"
+    ) -> Result<(), Error> {
         // apply RSA permutation
         for i in 0..2 {
-            let old = self.s[i].to_owned()?;
-            rsa_p(ctx, &mut self.s[i], &old, n)?;
+            rsa_p(&mut ws.ctx, &mut ws.new, &mut ws.tmp, &self.s[i], mont)?;
+            mem::swap(&mut self.s[i], &mut ws.new);
         }
 
         // apply feistel
-        self.feistel(ctx, true, n, tweak)?;
+        self.feistel_inv(ws, prf, n, mont, tweak)?;
         Ok(())
     }
 
-    // 1 round of encoding
-    fn round(
-        &mut self,
-        ctx: &mut BigNumContextRef,
-        n: &BigNum,   // modulus
-        t: &BigNum,   // trapdoor
-        tweak: &[u8], // tweak for "invertable RO"
-    ) -> Result<(), ErrorStack> {
-        // apply feistel
+    /* Encodes the blocks in lockstep:
+     * every Feistel round first evaluates the round function for all blocks,
+     * then inverts the outputs simultaneously (see Workspace::invert_batch)
+     * rather than computing a modular inverse per block.
+     */
+    fn encode_batch(
+        ws: &mut Workspace,
+        blocks: &mut [Pending],
+        n: &BigNum,         // modulus
+        mont: &MontContext, // Montgomery setup for n
+        t: &Trapdoor,
+    ) -> Result<(), Error> {
+        let k = blocks.len();
+        ws.reserve_batch(k)?;
 
-        self.feistel(ctx, false, n, tweak)?;
+        // apply rounds of leakage resiliant trapdoor
+        for _r in 0..Self::ROUNDS {
+            // apply feistel
+            for r in 0..Self::FEISTEL {
+                debug_assert!(r < 256);
 
-        // apply trapdoor
+                // P = F(b[0]) for every block
+                for (i, p) in blocks.iter().enumerate() {
+                    debug_assert_eq!(p.block.s[0].ucmp(n), Ordering::Less);
+                    debug_assert_eq!(p.block.s[1].ucmp(n), Ordering::Less);
 
-        for i in 0..2 {
-            let old = self.s[i].to_owned()?;
-            rsa_pinv(ctx, &mut self.s[i], &old, n, t)?;
-        }
+                    // round number || block tweak
+                    ws.tweak.clear();
+                    ws.tweak.push(r as u8);
+                    ws.tweak.extend_from_slice(p.tweak.as_bytes());
 
-        Ok(())
-    }
+                    ws.f(p.prf, &p.block.s[0], n)?;
+                    ws.batch[i].mont_form(&ws.p, mont, &mut ws.ctx)?;
+                }
 
-    pub fn encode(
-        &mut self,
-        ctx: &mut BigNumContextRef,
-        n: &BigNum,     // modulus
-        t: &BigNum,     // trapdoor
-        shard_idx: u16, // shard index in codeword
-        block_idx: u32, // block index in shard
-    ) -> Result<(), ErrorStack> {
-        // serialize tweak
-        let tweak = block_to_tweak(shard_idx, block_idx);
+                // P^-1 for every block
+                ws.invert_batch(k, n, mont)?;
 
-        // apply rounds of leakage resiliant trapdoor
-        for _r in 0..Self::ROUNDS {
-            self.round(ctx, n, t, &tweak[..])?;
+                // b[1] *= P^-1
+                for (i, p) in blocks.iter_mut().enumerate() {
+                    ws.new
+                        .mont_mul(&p.block.s[1], &ws.batch[i], mont, &mut ws.ctx)?;
+                    p.block.shift(&mut ws.new, r);
+                }
+            }
+
+            // apply trapdoor
+            for p in blocks.iter_mut() {
+                for i in 0..2 {
+                    t.apply(
+                        &mut ws.ctx,
+                        &mut ws.new,
+                        &mut ws.p,
+                        &mut ws.inv,
+                        &p.block.s[i],
+                    )?;
+                    mem::swap(&mut p.block.s[i], &mut ws.new);
+                }
+            }
         }
         Ok(())
     }
 
     pub fn decode(
         &mut self,
-        ctx: &mut BigNumContextRef,
-        n: &BigNum,     // modulus
-        shard_idx: u16, // shard index in codeword
-        block_idx: u32, // block index in shard
-    ) -> Result<(), ErrorStack> {
-        // serialize tweak
-        let tweak = block_to_tweak(shard_idx, block_idx);
-
+        ws: &mut Workspace,
+        prf: PrfKind,       // PRF of the "invertable RO"
+        n: &BigNum,         // modulus
+        mont: &MontContext, // Montgomery setup for n
+        tweak: &[u8],       // serialized block position (see block_to_tweak)
+    ) -> Result<(), Error> {
         // apply rounds of permutation
         for _r in 0..Self::ROUNDS {
-            self.round_inv(ctx, n, &tweak[..])?;
+            self.round_inv(ws, prf, n, mont, tweak)?;
         }
         Ok(())
     }
 }
 
+// res = v^3 mod n
 pub fn rsa_p(
-    ctx: &mut BigNumContextRef,
+    ctx: &mut BigNumContext,
     res: &mut BigNum,
+    tmp: &mut BigNum, // scratch
     v: &BigNum,
-    n: &BigNum,
-) -> Result<(), ErrorStack> {
-    let mut tmp = BigNum::new()?;
-    tmp.mod_sqr(v, n, ctx)?;
-    res.mod_mul(&tmp, v, n, ctx)
-}
+    mont: &MontContext,
+) -> Result<(), Error> {
+    // v R
+    res.mont_form(v, mont, ctx)?;
 
-pub fn rsa_pinv(
-    ctx: &mut BigNumContextRef,
-    res: &mut BigNum,
-    v: &BigNum,
-    n: &BigNum,
-    t: &BigNum,
-) -> Result<(), ErrorStack> {
-    res.mod_exp(v, t, n, ctx)
+    // v^2 R = (v R)(v R) R^-1
+    tmp.mont_mul(res, res, mont, ctx)?;
+
+    // v^3 = (v^2 R) v R^-1
+    res.mont_mul(tmp, v, mont, ctx)
 }
 
-fn generate(ctx: &mut BigNumContextRef, n: &mut BigNum, d: &mut BigNum) -> Result<(), ErrorStack> {
+fn generate(
+    ctx: &mut BigNumContext,
+    n: &mut BigNum,
+    d: &mut BigNum,
+    p: &mut BigNum,
+    q: &mut BigNum,
+) -> Result<(), Error> {
     let e = BigNum::from_u32(3)?;
     let mut tmp = BigNum::new()?;
-    let mut p = BigNum::new()?;
-    let mut q = BigNum::new()?;
-
-    p.generate_prime(PRIME_SIZE as i32, false, None, None)?;
-    q.generate_prime(PRIME_SIZE as i32, false, None, None)?;
-    n.checked_mul(&p, &q, ctx)?;
-
-    p.sub_word(1)?;
-    q.sub_word(1)?;
+    let mut p1 = BigNum::new()?;
+    let mut q1 = BigNum::new()?;
+
+    // p, q = 2 mod 3 ensures that e = 3 is invertible mod (p - 1)(q - 1)
+    p.generate_prime(PRIME_SIZE, 3, 2)?;
+    q.generate_prime(PRIME_SIZE, 3, 2)?;
+    n.checked_mul(p, q, ctx)?;
+
+    p1.copy_from(p)?;
+    q1.copy_from(q)?;
+    p1.sub_word(1)?;
+    q1.sub_word(1)?;
+
+    tmp.checked_mul(&p1, &q1, ctx)?;
+    let res = d.mod_inverse(&e, &tmp, ctx);
+
+    // wipe (p - 1)(q - 1)
+    p1.clear();
+    q1.clear();
+    tmp.clear();
+    res
+}
 
-    tmp.checked_mul(&p, &q, ctx)?;
-    d.mod_inverse(&e, &tmp, ctx)
+impl Default for EncodingKey {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl EncodingKey {
@@ -246,38 +582,118 @@ impl EncodingKey {
         let mut ctx = BigNumContext::new().unwrap();
         let mut n = BigNum::new().unwrap();
         let mut d = BigNum::new().unwrap();
-        generate(&mut ctx, &mut n, &mut d).unwrap();
-        EncodingKey { ctx, n, d }
+        let mut p = BigNum::new().unwrap();
+        let mut q = BigNum::new().unwrap();
+        generate(&mut ctx, &mut n, &mut d, &mut p, &mut q).unwrap();
+        Self::from_parts(n, d, p, q).unwrap()
+    }
+
+    fn from_parts(n: BigNum, d: BigNum, p: BigNum, q: BigNum) -> Result<Self, Error> {
+        let mut ws = Workspace::new()?;
+        let mont = MontContext::new(&n, &mut ws.ctx)?;
+        let crt = Trapdoor::new(&mut ws.ctx, &d, p, q)?;
+        Ok(EncodingKey {
+            ws,
+            n,
+            d,
+            mont,
+            crt,
+        })
     }
 
     pub fn encode(&mut self, s: &mut EncodedShard) {
-        for (i, block) in s.blocks.iter_mut().enumerate() {
-            block
-                .encode(&mut self.ctx, &self.n, &self.d, s.idx, i as u32)
-                .unwrap();
+        self.encode_batch(slice::from_mut(s))
+    }
+
+    /// Encodes a batch of shards,
+    /// the result is the same as encoding every shard in turn,
+    /// but the blocks of all shards share the modular inversions.
+    pub fn encode_batch(&mut self, shards: &mut [EncodedShard]) {
+        let fp = self.fingerprint();
+        let count = shards.iter().map(|s| s.blocks.len()).sum();
+        let mut blocks: Vec<Pending> = Vec::with_capacity(count);
+        for s in shards.iter_mut() {
+            s.key = Some(fp);
+            for (i, block) in s.blocks.iter_mut().enumerate() {
+                blocks.push(Pending {
+                    block,
+                    prf: s.prf,
+                    tweak: block_to_tweak(s.idx, s.replica, i as u32),
+                });
+            }
         }
+        EncodeBlock::encode_batch(
+            &mut self.ws,
+            &mut blocks[..],
+            &self.n,
+            &self.mont,
+            &self.crt,
+        )
+        .unwrap();
     }
 
-    pub fn decoding(&self) -> DecodingKey {
-        DecodingKey {
-            ctx: BigNumContext::new().unwrap(),
-            n: self.n.to_owned().unwrap(),
+    /// Packs and encodes a shard
+    pub fn encode_shard(&mut self, s: &Shard) -> EncodedShard {
+        let mut e = s.pack();
+        self.encode(&mut e);
+        e
+    }
+
+    /// Splits the file into shards (with expansion parity shards, see File::shards)
+    /// and encodes them all, the header records the key
+    pub fn encode_file(
+        &mut self,
+        file: &File,
+        expansion: usize,
+    ) -> Result<(Header, Vec<EncodedShard>), Error> {
+        let (mut header, shards) = file.shards(expansion)?;
+        header.set_fingerprint(self.fingerprint());
+        let mut enc: Vec<EncodedShard> = shards.iter().map(|s| s.pack()).collect();
+        self.encode_batch(&mut enc[..]);
+        Ok((header, enc))
+    }
+
+    /// Re-encodes a shard under this key:
+    /// decodes it using the old key and encodes it again,
+    /// optionally as a different replica.
+    pub fn rotate(
+        &mut self,
+        old: &mut DecodingKey,
+        s: &mut EncodedShard,
+        replica: Option<u32>,
+    ) -> Result<(), Error> {
+        old.decode(s)?;
+        if let Some(replica) = replica {
+            s.replica = replica;
         }
+        self.encode(s);
+        Ok(())
     }
 
-    pub fn serialize(&self) -> Vec<u8> {
+    /// Returns the fingerprint of the (public) modulus
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::of_modulus(&self.n)
+    }
+
+    pub fn decoding(&self) -> DecodingKey {
+        DecodingKey::from_modulus(self.n.clone()).unwrap()
+    }
+
+    /// Serializes the encoding key,
+    /// the buffer is wiped when dropped
+    pub fn serialize(&self) -> Zeroizing<Vec<u8>> {
         let n = self.n.to_vec();
-        let d = self.d.to_vec();
-        let mut ser = Vec::with_capacity(n.len() + d.len() + 2);
+        let d = Zeroizing::new(self.d.to_vec());
+        let mut ser = Zeroizing::new(Vec::with_capacity(n.len() + d.len() + 2));
         ser.extend(&(n.len() as u16).to_be_bytes());
         ser.extend(n);
-        ser.extend(d);
+        ser.extend(&d[..]);
         ser
     }
 
-    pub fn deserialize(bytes: &[u8]) -> Result<Self, ()> {
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
         if bytes.len() < 2 {
-            return Err(());
+            return Err(Error::Malformed);
         }
 
         // load length of n
@@ -285,33 +701,87 @@ impl EncodingKey {
         l.copy_from_slice(&bytes[..2]);
         let l: usize = u16::from_be_bytes(l) as usize;
         if l >= bytes.len() - 2 {
-            return Err(());
+            return Err(Error::Malformed);
         }
 
         // split into n and d
-        let ctx = BigNumContext::new().unwrap();
-        let n = BigNum::from_slice(&bytes[2..2 + l]).map_err(|_| ())?;
-        let d = BigNum::from_slice(&bytes[2 + l..]).map_err(|_| ())?;
-        Ok(EncodingKey { ctx, d, n })
+        let mut ws = Workspace::new()?;
+        let n = BigNum::from_slice(&bytes[2..2 + l])?;
+        let d = BigNum::from_slice(&bytes[2 + l..])?;
+
+        // the factorization is not stored
+        let mont = MontContext::new(&n, &mut ws.ctx).map_err(|_| Error::Malformed)?;
+        let crt = Trapdoor::recover(&mut ws.ctx, &n, &d)?;
+        Ok(EncodingKey {
+            ws,
+            n,
+            d,
+            mont,
+            crt,
+        })
     }
 }
 
 impl DecodingKey {
-    pub fn decode(&mut self, s: &mut EncodedShard) {
+    /// Decodes the shard,
+    /// fails if the shard records that it was encoded under a different key
+    pub fn decode(&mut self, s: &mut EncodedShard) -> Result<(), Error> {
+        self.check(s.key.as_ref())?;
         for (i, block) in s.blocks.iter_mut().enumerate() {
-            block
-                .decode(&mut self.ctx, &self.n, s.idx, i as u32)
-                .unwrap();
+            let tweak = block_to_tweak(s.idx, s.replica, i as u32);
+            block.decode(&mut self.ws, s.prf, &self.n, &self.mont, tweak.as_bytes())?;
         }
+        s.key = None;
+        Ok(())
+    }
+
+    /// Decodes and unpacks a shard, leaving the encoded shard untouched
+    pub fn decode_shard(&mut self, s: &EncodedShard) -> Result<Shard, Error> {
+        let mut s = s.clone();
+        self.decode(&mut s)?;
+        s.unpack()
+    }
+
+    /// Decodes the shards and reconstructs the file,
+    /// any subset of (at least header.shards()) shards suffices
+    pub fn decode_file(&mut self, header: &Header, shards: &[EncodedShard]) -> Result<File, Error> {
+        self.check_header(header)?;
+        let dec = shards
+            .iter()
+            .map(|s| self.decode_shard(s))
+            .collect::<Result<Vec<Shard>, Error>>()?;
+        File::reconstruct(header, &dec[..])
+    }
+
+    /// Checks that the header does not name a different key
+    pub fn check_header(&self, header: &Header) -> Result<(), Error> {
+        self.check(header.key.as_ref())
+    }
+
+    fn check(&self, key: Option<&Fingerprint>) -> Result<(), Error> {
+        match key {
+            Some(fp) if *fp != self.fingerprint() => Err(Error::KeyMismatch),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the fingerprint of the modulus
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::of_modulus(&self.n)
     }
 
     pub fn serialize(&self) -> Vec<u8> {
         self.n.to_vec()
     }
 
-    pub fn deserialize(bytes: &[u8]) -> Result<Self, ()> {
-        let ctx = BigNumContext::new().unwrap();
-        let n = BigNum::from_slice(bytes).map_err(|_| ())?;
-        Ok(DecodingKey { ctx, n })
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let n = BigNum::from_slice(bytes)?;
+        Self::from_modulus(n)
+    }
+
+    fn from_modulus(n: BigNum) -> Result<Self, Error> {
+        let mut ws = Workspace::new()?;
+        let mont = MontContext::new(&n, &mut ws.ctx).map_err(|_| Error::Malformed)?;
+        Ok(DecodingKey { ws, n, mont })
     }
 }
@@ -0,0 +1,128 @@
+use std::fmt;
+
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use super::misc::Reader;
+use super::{DecodingKey, EncodedShard, Error, Header, PrfKind, Shard};
+
+/* serde support (feature "serde")
+ *
+ * Every object is represented by its binary serialization
+ * (in which bignums are fixed-width), as a byte string for binary formats (e.g. CBOR)
+ * and as a hex string for human-readable formats (e.g. JSON).
+ *
+ * A shard is serialized as: index (u16) || PRF id (u8) || contents
+ */
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn serialize_bytes<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&to_hex(bytes))
+    } else {
+        serializer.serialize_bytes(bytes)
+    }
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a byte string or hex string")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(v)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        from_hex(v).ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+
+    // formats without a byte string type
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        // the length hint comes from the input, so is capped
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(b) = seq.next_element()? {
+            bytes.push(b);
+        }
+        Ok(bytes)
+    }
+}
+
+fn deserialize_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(BytesVisitor)
+    } else {
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+impl Shard {
+    fn to_wire(&self) -> Vec<u8> {
+        let mut ser = Vec::with_capacity(2 + 1 + self.coords.len() * 2);
+        ser.extend(&self.idx.to_be_bytes());
+        ser.push(self.prf.id());
+        ser.extend(self.as_bytes());
+        ser
+    }
+
+    fn from_wire(bytes: &[u8]) -> Result<Self, Error> {
+        let mut rd = Reader::new(bytes);
+        let idx = rd.u16()?;
+        let prf = PrfKind::from_id(rd.u8()?)?;
+        let mut shard = Shard::from_bytes(idx, rd.take(rd.remaining())?)?;
+        shard.set_prf(prf);
+        Ok(shard)
+    }
+}
+
+macro_rules! serde_via_bytes {
+    ($ty:ty, $ser:expr, $de:expr) => {
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize_bytes(&$ser(self)[..], serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let bytes = deserialize_bytes(deserializer)?;
+                $de(&bytes[..]).map_err(de::Error::custom)
+            }
+        }
+    };
+}
+
+serde_via_bytes!(Header, Header::serialize, Header::deserialize);
+serde_via_bytes!(Shard, Shard::to_wire, Shard::from_wire);
+serde_via_bytes!(
+    EncodedShard,
+    EncodedShard::serialize,
+    EncodedShard::deserialize
+);
+serde_via_bytes!(
+    DecodingKey,
+    DecodingKey::serialize,
+    DecodingKey::deserialize
+);
@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+
+use super::{DecodingKey, EncodedShard, EncodingKey, Error};
+
+/// Storage of encoded shards, indexed by shard index
+pub trait ShardStore {
+    /// Returns the indices of the shards held
+    fn indices(&self) -> Result<Vec<u16>, Error>;
+
+    /// Loads the shard with the given index (if held)
+    fn load(&mut self, idx: u16) -> Result<Option<EncodedShard>, Error>;
+
+    /// Stores a shard, replacing any shard with the same index
+    fn store(&mut self, shard: EncodedShard) -> Result<(), Error>;
+}
+
+impl ShardStore for BTreeMap<u16, EncodedShard> {
+    fn indices(&self) -> Result<Vec<u16>, Error> {
+        Ok(self.keys().cloned().collect())
+    }
+
+    fn load(&mut self, idx: u16) -> Result<Option<EncodedShard>, Error> {
+        Ok(self.get(&idx).cloned())
+    }
+
+    fn store(&mut self, shard: EncodedShard) -> Result<(), Error> {
+        self.insert(shard.idx, shard);
+        Ok(())
+    }
+}
+
+impl EncodingKey {
+    /// Re-encodes every shard in the store under this key,
+    /// one shard at a time. Shards already recorded as encoded under
+    /// this key are skipped, so an interrupted rotation can be resumed.
+    /// Returns the number of shards rotated.
+    ///
+    /// Fails with Error::UnknownKey on a shard which does not record its key
+    /// (it may already be encoded under this key), see EncodedShard::set_fingerprint.
+    pub fn rotate_store<S: ShardStore>(
+        &mut self,
+        old: &mut DecodingKey,
+        store: &mut S,
+        replica: Option<u32>,
+    ) -> Result<usize, Error> {
+        let fp = self.fingerprint();
+        let indices = store.indices()?;
+        let mut rotated = 0;
+        for idx in indices {
+            if let Some(mut s) = store.load(idx)? {
+                match s.key {
+                    // already rotated
+                    Some(key) if key == fp => continue,
+                    // decoding checks that it is the old key
+                    Some(_) => (),
+                    None => return Err(Error::UnknownKey),
+                }
+                self.rotate(old, &mut s, replica)?;
+                store.store(s)?;
+                rotated += 1;
+            }
+        }
+        Ok(rotated)
+    }
+}
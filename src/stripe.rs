@@ -0,0 +1,139 @@
+use super::misc::Reader;
+use super::{EncodedShard, EncodingKey, Error, File, FileKey, Header, Params};
+
+/* Append-only objects
+ *
+ * An object is a log of stripes, every stripe is a File split on its own
+ * (with its own header and shards), linked by a root manifest
+ * listing the headers of the stripes in order.
+ * Appending splits and encodes only the new stripe,
+ * updating a stripe replaces only its header in the root manifest.
+ *
+ * version || count (u32) || (len (u32) || header)*
+ */
+
+/// Root manifest of an object stored as a log of stripes
+#[derive(Debug, Default)]
+pub struct RootManifest {
+    stripes: Vec<Header>,
+}
+
+impl RootManifest {
+    const VERSION: u8 = 1;
+
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Appends the header of a new stripe, returning the index of the stripe
+    pub fn push(&mut self, header: Header) -> usize {
+        self.stripes.push(header);
+        self.stripes.len() - 1
+    }
+
+    /// Replaces the header of a stripe (after re-splitting its contents)
+    pub fn replace(&mut self, stripe: usize, header: Header) -> Result<(), Error> {
+        match self.stripes.get_mut(stripe) {
+            Some(h) => {
+                *h = header;
+                Ok(())
+            }
+            None => Err(Error::NotFound),
+        }
+    }
+
+    /// Returns the headers of the stripes in order
+    pub fn stripes(&self) -> &[Header] {
+        &self.stripes
+    }
+
+    /// Returns the length of the contents of the object
+    pub fn length(&self) -> usize {
+        self.stripes.iter().map(|h| h.original_length()).sum()
+    }
+
+    /// Returns the stripes covering the bytes [offset, offset + len) of the object
+    /// (truncated at the end of the object), as (stripe, offset, len) within every stripe
+    pub fn locate(&self, offset: usize, len: usize) -> Vec<(usize, usize, usize)> {
+        let end = offset.saturating_add(len);
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        for (i, h) in self.stripes.iter().enumerate() {
+            let stop = start + h.original_length();
+            let (l, r) = (offset.max(start), end.min(stop));
+            if l < r {
+                ranges.push((i, l - start, r - l));
+            }
+            start = stop;
+        }
+        ranges
+    }
+
+    /// Joins the contents of the (reconstructed) stripes:
+    /// encrypted stripes are decrypted with the key (see File::unpack_with_key),
+    /// failing with Error::Encrypted if none is given,
+    /// the others are checked against the digest in their header (see File::unpack_verified).
+    /// Fails with Error::Digest if a stripe does not match its header
+    /// (e.g. stripes out of order or from another object).
+    pub fn unpack(&self, stripes: &[File], key: Option<&FileKey>) -> Result<Vec<u8>, Error> {
+        if stripes.len() != self.stripes.len() {
+            return Err(Error::Reconstruct);
+        }
+        let mut data = Vec::with_capacity(self.length());
+        for (file, header) in stripes.iter().zip(self.stripes.iter()) {
+            if file.digest != header.digest
+                || file.seal != header.seal
+                || file.length != header.length
+                || file.original != header.original
+            {
+                return Err(Error::Digest);
+            }
+            data.extend(match (&file.seal, key) {
+                (Some(_), Some(key)) => file.unpack_with_key(key)?,
+                _ => file.unpack_verified()?,
+            });
+        }
+        Ok(data)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut ser = vec![Self::VERSION];
+        ser.extend(&(self.stripes.len() as u32).to_be_bytes());
+        for h in self.stripes.iter() {
+            let h = h.serialize();
+            ser.extend(&(h.len() as u32).to_be_bytes());
+            ser.extend(h);
+        }
+        ser
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let mut rd = Reader::new(bytes);
+        if rd.u8()? != Self::VERSION {
+            return Err(Error::Malformed);
+        }
+        let count = rd.u32()? as usize;
+        let mut stripes = Vec::with_capacity(count.min(bytes.len()));
+        for _ in 0..count {
+            let len = rd.u32()? as usize;
+            stripes.push(Header::deserialize(rd.take(len)?)?);
+        }
+        rd.finish()?;
+        Ok(RootManifest { stripes })
+    }
+}
+
+impl EncodingKey {
+    /// Splits and encodes data as a new stripe of the object,
+    /// returning the index of the stripe and its encoded shards
+    pub fn append(
+        &mut self,
+        root: &mut RootManifest,
+        data: &[u8],
+        params: &Params,
+        expansion: usize,
+    ) -> Result<(usize, Vec<EncodedShard>), Error> {
+        let (header, shards) = self.encode_file(&File::with_params(data, params), expansion)?;
+        Ok((root.push(header), shards))
+    }
+}
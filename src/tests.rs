@@ -1,15 +1,64 @@
 use rand::Rng;
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
 use test::Bencher;
 
 use super::*;
 
+// fixed encoding key for known-answer tests
+const KAT_KEY: &str = concat!(
+    "010102a780ad139f7ea1556328aa5742b74ba6e165e543245440c5419d539eb8d72aca84968bffdc",
+    "5d9061cadea740e5288570cd586a36f9ff1ddbf0df9144e43e851397e1a2ebc15f8850d039c294a6",
+    "39d6683965b704c4856914a28b204e94a6a4c8545de8eb161cf5d57a8c4a6f4f83cbdbadf97429bd",
+    "19e52f8c2b478cb0f98e03f3a7aa5d1e3db7f96e7bbd8f18e15f4714228ad8e1876ca1aaa34d73df",
+    "d1ad3d1046bfaf96b4c2ab1adfaf1778a0925920da626181e69ca8af3a791a3a2bda3a56060f3009",
+    "4f779b98053dd58635e4452bbdc59a8652aade51c73584cab5a056df1df960c91a99c0a16e6fec95",
+    "36bdb13a833fe32101d35199ccaf41274c742d01c50073626a546b8e421b1c3a2c7a326f40ee98d7",
+    "6d8d808381138d147b3a1c87030f07ffe83e604131e9c4d5ee1b03a088e59c24a6aa13e7f5ea60d8",
+    "9829ae0d0febc1f280ea5ae08ad12c63197be4457b9924add858f0b86c5cc0346319c330383e9b47",
+    "64134e8e51b2dc4a350287e7c950f81bd36698ca5d722fb320a65eab1aa0e1bbe2ff8ed4035510a1",
+    "463072bbf0a1dfc93f4d68e63bca6532c083cee6af68443a5b2059f57d2e8d9c14b257df179fce2b",
+    "768ea983a4095d161a666a1f2bcb8fb0eb80e306a72508bf431eea2af86c9a6d1461d1927f73acda",
+    "3406c7fd2670985ee62d2c5b909d78b015be9f822036738e22eab39b23ca195d16362213",
+);
+
+// blocks of the shard in known_answer, encoded as replica 0 by the original (baseline) scheme
+const BASELINE_BLOCKS: &str = concat!(
+    "00ef3109b97c15fd076fd3dd868f6d02f6561f03a5f16532616ec7a43b1fd573ee0e1b27f1f1b3ed",
+    "cc19170b3b4556a7742b0b178a613c339ae27139ece69c3d78c5e68011cde42d135975c011fb2b46",
+    "38bce8f8d8e670265c424f6b64655798e477d81ed9d2afa09c79953458016cbba1113a68a47f999f",
+    "9527f9fac7039eb4c0d094366c6a50969e34eaa2c6d4cf7d32952bffb3d200d964a40c399c1d471a",
+    "d16f38e374f2b9314efb3128aa2a10c330d4cbdc73076508c72628040e7d6b1b6d3f6ee11301b6b9",
+    "b5e3768a6eb2ddb76543fa3b7d37319969b5d0759764a2da4ca717cc67dc4a4fefe3bc570c67ad8f",
+    "013a6193605478944d9789c34e2e1ee72d0143c36357fea8d338963c0289dc160ae23f5338cb2845",
+    "72e6beeba3ab7485a4411403790d8bf06c429a650f67a25189b24544ddb67c29d7bce208d8e6c8a0",
+    "1af4a3add9f8b3e12d39bf203a1b91c1c8c31e4863feae87cafdcefa2758cae360ffa9084bd18f4e",
+    "698ac611be40d26a910f19655771710452060ffed2b5acaeaebd3d6b77c71e0c871095f695cca03b",
+    "c7c2c70ee9d65a06b6aa0b274d259b209f4c7be2d7287a29240ca0c32ca7945aad3af0aeddbb890a",
+    "1963a31965e468464d1790ba9c226e975268227c1c6daa6f6239a432f6a0c0255c900d790a255390",
+    "eac2c4ed43fdd054630ba67af8a7386a3a12b106b0b88b68af907a9ce8ab60b9c8d90165bf16cab6",
+    "438f42129edb4872d62998f2194a604f171e8d9589f79e9861e9d3f0fe1a6250e808074484a6a0f0",
+    "7856a4e309cb7d7182ae1b345bb0f039e4a103f30170028b1de83f634c1f55e9b345e15b49421f74",
+    "096dd38f8224c917c6020f8b1f4121764ffecac096e400a8d8d1af7cd9c26377f8be02c140d828bf",
+    "bf7c162862a9330ceaa338c97778bc59c3e4c659f3888297f7e66bf6a040cb707dde1565a34dd2f6",
+    "17324f7bb0465c325256269a19aec95dd514c135b8653b60570b2bc52fe042e2493fbe1b01551f1a",
+    "de0d4e6e3d13d3e4a869bafcb6225fda5f6dce22c5d24bc86d936259dc6b5683be4c0ecea093ef86",
+    "489254d7ca990be0249dd2022fa3bd55dfb1574959b2c08cf6a211f8f1ac7ada595bff4b0a1bf053",
+    "1183edceebd3be5711c62a0562ea2dc505608b6317cb6711ac1623e276d7f68dbfbf3b9d404b74a9",
+    "7f84bd472b365cbd5729998bf93776d6ac150f357169478df27c10ccde081363d9c65e99e27f5230",
+    "7bd6099f7beab4f81bf7bd476052081855097a90332e022c2a0c4d320829b9875b686a40a4c06bbb",
+    "bf37307972717a571e8809d72039352a220f12ec8d8b04d27086bde7b56311e0fef64a3092837459",
+    "91d8a9d166180bc0d8b9c65cedd829dbe4c3b11fa56f05b9a06bf03a90462fb4626311595e2de19b",
+    "14e9a5ff01f3d2df54a6b3f29b269c3cb137591258e417cf2f978827",
+);
+
 #[bench]
 fn encode(b: &mut Bencher) {
     let mut sk = EncodingKey::new();
     let bytes = vec![0; 1024];
 
     let file = File::new(&bytes[..]);
-    let (_, shards) = file.shards(0);
+    let (_, shards) = file.shards(0).unwrap();
 
     let mut enc: Vec<EncodedShard> = shards.into_iter().map(|s| s.pack()).collect();
 
@@ -20,6 +69,33 @@ fn encode(b: &mut Bencher) {
     });
 }
 
+// shards of a 16 KB file, encoded one at a time or as a single batch
+fn bench_shards() -> Vec<EncodedShard> {
+    let bytes = vec![0; 16 * SHARD_SIZE];
+    let (_, shards) = File::new(&bytes[..]).shards(0).unwrap();
+    shards.into_iter().map(|s| s.pack()).collect()
+}
+
+#[bench]
+fn encode_shards(b: &mut Bencher) {
+    let mut sk = EncodingKey::new();
+    let mut enc = bench_shards();
+
+    b.iter(|| {
+        for s in enc.iter_mut() {
+            sk.encode(s)
+        }
+    });
+}
+
+#[bench]
+fn encode_batch(b: &mut Bencher) {
+    let mut sk = EncodingKey::new();
+    let mut enc = bench_shards();
+
+    b.iter(|| sk.encode_batch(&mut enc[..]));
+}
+
 #[bench]
 fn decode(b: &mut Bencher) {
     let sk = EncodingKey::new();
@@ -27,17 +103,38 @@ fn decode(b: &mut Bencher) {
     let mut pk = sk.decoding();
 
     let file = File::new(&bytes[..]);
-    let (_, shards) = file.shards(0);
+    let (_, shards) = file.shards(0).unwrap();
 
     let mut enc: Vec<EncodedShard> = shards.into_iter().map(|s| s.pack()).collect();
 
     b.iter(|| {
         for s in enc.iter_mut() {
-            pk.decode(s)
+            pk.decode(s).unwrap()
         }
     });
 }
 
+fn bench_prf(b: &mut Bencher, prf: PrfKind) {
+    let value = vec![0x42; 256];
+    let mut out = vec![0; 273];
+    b.iter(|| prf.prf().expand(&[1, 2, 3], &value[..], &mut out[..]));
+}
+
+#[bench]
+fn prf_sha256_aes_ctr(b: &mut Bencher) {
+    bench_prf(b, PrfKind::Sha256AesCtr)
+}
+
+#[bench]
+fn prf_blake3(b: &mut Bencher) {
+    bench_prf(b, PrfKind::Blake3)
+}
+
+#[bench]
+fn prf_shake256(b: &mut Bencher) {
+    bench_prf(b, PrfKind::Shake256)
+}
+
 #[test]
 fn encode_decode() {
     // generate new encoding / decoding key
@@ -55,7 +152,7 @@ fn encode_decode() {
     // create file object and split into shards
     let file = File::new(&original[..]);
     let expand = rng.gen::<usize>() % 20;
-    let (header, shards) = file.shards(expand);
+    let (header, shards) = file.shards(expand).unwrap();
     assert_eq!(shards.len(), header.shards() + expand);
 
     // encode each shard
@@ -80,15 +177,1168 @@ fn encode_decode() {
     let dec: Vec<Shard> = enc
         .into_iter()
         .map(|mut e| {
-            pk.decode(&mut e);
-            e.unpack()
+            pk.decode(&mut e).unwrap();
+            e.unpack().unwrap()
         })
         .collect();
 
     // recover the file from the remaining shards
     let file2 = File::reconstruct(&header, &dec[..]).unwrap();
-    let recover = file2.unpack();
+    let recover = file2.unpack().unwrap();
 
     // check that we succesfully recovered
     assert_eq!(&original[..], &recover[..]);
 }
+
+#[test]
+fn encrypted_key() {
+    let sk = EncodingKey::new();
+    let ser = sk.serialize_encrypted(b"hunter2").unwrap();
+
+    // correct password recovers the key
+    let sk2 = EncodingKey::deserialize_encrypted(&ser[..], b"hunter2").unwrap();
+    assert_eq!(sk.serialize(), sk2.serialize());
+
+    // wrong password or tampering is detected
+    let mut bad = ser.clone();
+    *bad.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        EncodingKey::deserialize_encrypted(&ser[..], b"hunter3"),
+        Err(Error::Decrypt)
+    ));
+    assert!(matches!(
+        EncodingKey::deserialize_encrypted(&bad[..], b"hunter2"),
+        Err(Error::Decrypt)
+    ));
+}
+
+// the CRT trapdoor of a loaded key is rebuilt from (n, d)
+#[test]
+fn trapdoor() {
+    let mut sk = EncodingKey::new();
+    let ser = sk.serialize();
+    let mut sk2 = EncodingKey::deserialize(&ser[..]).unwrap();
+
+    let shard = Shard::new(1, PrfKind::default(), &[7; SHARD_SIZE]);
+    let mut e1 = shard.pack();
+    let mut e2 = shard.pack();
+    sk.encode(&mut e1);
+    sk2.encode(&mut e2);
+    assert_eq!(e1.serialize(), e2.serialize());
+
+    // a private exponent not matching the modulus is rejected
+    let mut bad = ser.to_vec();
+    *bad.last_mut().unwrap() ^= 2;
+    assert!(matches!(
+        EncodingKey::deserialize(&bad[..]),
+        Err(Error::Malformed)
+    ));
+
+    // as is an even modulus
+    assert!(matches!(
+        DecodingKey::deserialize(&[1, 0]),
+        Err(Error::Malformed)
+    ));
+}
+
+#[test]
+fn fingerprint() {
+    let sk = EncodingKey::new();
+    let mut pk = sk.decoding();
+    let mut other = EncodingKey::new().decoding();
+    assert_eq!(sk.fingerprint(), pk.fingerprint());
+    assert_ne!(sk.fingerprint(), other.fingerprint());
+
+    // fingerprint is recorded in the header
+    let file = File::new(&[1, 2, 3]);
+    let (mut header, shards) = file.shards(1).unwrap();
+    header.set_fingerprint(sk.fingerprint());
+    let header = Header::deserialize(&header.serialize()[..]).unwrap();
+    assert!(pk.check_header(&header).is_ok());
+    assert!(matches!(
+        other.check_header(&header),
+        Err(Error::KeyMismatch)
+    ));
+
+    // and in each serialized shard
+    let mut sk = sk;
+    let mut e = shards[0].pack();
+    sk.encode(&mut e);
+    let mut e = EncodedShard::deserialize(&e.serialize()[..]).unwrap();
+    assert_eq!(e.fingerprint(), Some(&pk.fingerprint()));
+    assert!(matches!(other.decode(&mut e), Err(Error::KeyMismatch)));
+    pk.decode(&mut e).unwrap();
+    assert_eq!(e.unpack().unwrap().unpack(), shards[0].unpack());
+}
+
+#[test]
+fn keyring() {
+    // two encoders share the work
+    let mut sk1 = EncodingKey::new();
+    let mut sk2 = EncodingKey::new();
+    let mut ring = KeyRing::new();
+    ring.insert(sk1.decoding());
+    ring.insert(sk2.decoding());
+
+    let original: Vec<u8> = (0..4000).map(|i| i as u8).collect();
+    let file = File::new(&original[..]);
+    let (mut header, shards) = file.shards(2).unwrap();
+    header.set_fingerprint(sk1.fingerprint());
+
+    let enc: Vec<EncodedShard> = shards
+        .into_iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let mut e = s.pack();
+            if i % 2 == 0 {
+                sk1.encode(&mut e);
+            } else {
+                sk2.encode(&mut e);
+                header.set_shard_fingerprint(i as u16, sk2.fingerprint());
+            }
+            // rely on the manifest rather than the shard
+            e.set_fingerprint(None);
+            e
+        })
+        .collect();
+
+    let header = Header::deserialize(&header.serialize()[..]).unwrap();
+    let dec = ring.decode_shards(&header, enc).unwrap();
+    let file2 = File::reconstruct(&header, &dec[..]).unwrap();
+    assert_eq!(&original[..], &file2.unpack().unwrap()[..]);
+
+    // a key missing from the ring is reported
+    let mut ring = KeyRing::new();
+    ring.insert(sk1.decoding());
+    let mut e = File::new(&original[..]).shards(2).unwrap().1[1].pack();
+    sk2.encode(&mut e);
+    assert!(matches!(
+        ring.decode(&header, &mut e),
+        Err(Error::UnknownKey)
+    ));
+}
+
+#[test]
+fn manifest_full() {
+    // every shard index has an entry
+    let (mut header, _) = File::new(&[1, 2, 3]).shards(1).unwrap();
+    for idx in 0..=u16::MAX {
+        header.set_shard_fingerprint(idx, Fingerprint::from_bytes([idx as u8; Fingerprint::SIZE]));
+    }
+    let header = Header::deserialize(&header.serialize()[..]).unwrap();
+    assert_eq!(header.manifest().len(), MAX_SHARDS);
+    for idx in [0, 1, 255, u16::MAX] {
+        assert_eq!(
+            header.manifest()[&idx],
+            Fingerprint::from_bytes([idx as u8; Fingerprint::SIZE])
+        );
+    }
+}
+
+#[test]
+fn rotate() {
+    let mut old_sk = EncodingKey::new();
+    let mut old_pk = old_sk.decoding();
+    let mut new_sk = EncodingKey::new();
+    let mut new_pk = new_sk.decoding();
+
+    let original: Vec<u8> = (0..3000).map(|i| (i * 7) as u8).collect();
+    let (header, shards) = File::new(&original[..]).shards(1).unwrap();
+
+    let mut store: BTreeMap<u16, EncodedShard> = BTreeMap::new();
+    for s in shards.iter() {
+        let mut e = s.pack();
+        old_sk.encode(&mut e);
+        store.store(e).unwrap();
+    }
+    let before = store[&0].serialize();
+
+    // shards which do not record their key are refused
+    let mut stripped = store.clone();
+    for e in stripped.values_mut() {
+        e.set_fingerprint(None);
+    }
+    assert!(matches!(
+        new_sk.rotate_store(&mut old_pk, &mut stripped, None),
+        Err(Error::UnknownKey)
+    ));
+    for e in stripped.values_mut() {
+        e.set_fingerprint(Some(old_pk.fingerprint()));
+    }
+    assert_eq!(
+        new_sk
+            .rotate_store(&mut old_pk, &mut stripped, None)
+            .unwrap(),
+        shards.len()
+    );
+    assert_eq!(
+        new_sk
+            .rotate_store(&mut old_pk, &mut stripped, None)
+            .unwrap(),
+        0
+    );
+
+    // rotate everything to the new key, as a new replica
+    assert_eq!(
+        new_sk
+            .rotate_store(&mut old_pk, &mut store, Some(1))
+            .unwrap(),
+        shards.len()
+    );
+    assert_eq!(
+        new_sk
+            .rotate_store(&mut old_pk, &mut store, Some(1))
+            .unwrap(),
+        0
+    );
+    assert_ne!(store[&0].serialize(), before);
+    assert_eq!(store[&0].replica(), 1);
+
+    let dec: Vec<Shard> = store
+        .into_values()
+        .map(|mut e| {
+            new_pk.decode(&mut e).unwrap();
+            e.unpack().unwrap()
+        })
+        .collect();
+    let file = File::reconstruct(&header, &dec[..]).unwrap();
+    assert_eq!(&original[..], &file.unpack().unwrap()[..]);
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn batch() {
+    let mut rng = rand::thread_rng();
+    let mut sk = EncodingKey::new();
+    let mut pk = sk.decoding();
+
+    let bytes: Vec<u8> = (0..5000).map(|_| rng.gen()).collect();
+    let file = File::new(&bytes[..]);
+    let (header, shards) = file.shards(2).unwrap();
+
+    // distinct replicas within one batch
+    let mut single: Vec<EncodedShard> = shards
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s.pack_replica(i as u32))
+        .collect();
+    let mut batch = single.clone();
+
+    for e in single.iter_mut() {
+        sk.encode(e);
+    }
+    sk.encode_batch(&mut batch[..]);
+    sk.encode_batch(&mut []);
+
+    let mut decoded = Vec::new();
+    for (a, mut b) in single.iter().zip(batch) {
+        assert_eq!(a.serialize(), b.serialize());
+        pk.decode(&mut b).unwrap();
+        decoded.push(b.unpack().unwrap());
+    }
+    let file = File::reconstruct(&header, &decoded[..]).unwrap();
+    assert_eq!(file.unpack().unwrap(), bytes);
+}
+
+// both arithmetic backends must produce bit-identical encodings
+#[test]
+fn known_answer() {
+    let mut sk = EncodingKey::deserialize(&from_hex(KAT_KEY)[..]).unwrap();
+    let mut pk = sk.decoding();
+
+    let bytes: Vec<u8> = (0..1024).map(|i| i as u8).collect();
+    let shard = Shard::new(3, PrfKind::default(), &bytes[..]);
+    let mut e = shard.pack_replica(7);
+    sk.encode(&mut e);
+
+    let ser = e.serialize();
+    let digest: String = primitives::sha256(&ser[..])
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(
+        digest,
+        "70be6f683382a3ee0b38ca85f03eb5553d83b41f0c42feba53cc6ea36e45d54e"
+    );
+
+    pk.decode(&mut e).unwrap();
+    assert_eq!(e.unpack().unwrap().unpack(), bytes);
+}
+
+// replica 0 uses the tweak of the baseline scheme,
+// so shards encoded before replicas were added still decode
+#[test]
+fn baseline_tweak() {
+    let mut sk = EncodingKey::deserialize(&from_hex(KAT_KEY)[..]).unwrap();
+    let mut pk = sk.decoding();
+
+    let bytes: Vec<u8> = (0..1024).map(|i| i as u8).collect();
+    let shard = Shard::new(3, PrfKind::default(), &bytes[..]);
+
+    // index 3 || replica 0 || default PRF || no fingerprint || blocks
+    let mut ser = vec![0, 3, 0, 0, 0, 0, PrfKind::default().id(), 0];
+    ser.extend(from_hex(BASELINE_BLOCKS));
+    let mut e = EncodedShard::deserialize(&ser[..]).unwrap();
+    pk.decode(&mut e).unwrap();
+    assert_eq!(e.unpack().unwrap().as_bytes(), &bytes[..]);
+
+    // and encoding replica 0 reproduces it
+    let mut e = shard.pack();
+    sk.encode(&mut e);
+    e.set_fingerprint(None);
+    assert_eq!(e.serialize(), ser);
+}
+
+#[test]
+fn prf_params() {
+    let mut sk = EncodingKey::new();
+    let mut pk = sk.decoding();
+    let original: Vec<u8> = (0..2000).map(|i| (i % 251) as u8).collect();
+
+    for prf in [PrfKind::Sha256AesCtr, PrfKind::Blake3, PrfKind::Shake256] {
+        let params = Params {
+            prf,
+            ..Default::default()
+        };
+        let (header, shards) = File::with_params(&original[..], &params).shards(1).unwrap();
+
+        // recorded in the header
+        let header = Header::deserialize(&header.serialize()[..]).unwrap();
+        assert_eq!(header.prf, prf);
+
+        let dec: Vec<Shard> = shards
+            .into_iter()
+            .skip(1)
+            .map(|s| {
+                let mut e = s.pack();
+                sk.encode(&mut e);
+                let mut e = EncodedShard::deserialize(&e.serialize()[..]).unwrap();
+                pk.decode(&mut e).unwrap();
+                e.unpack().unwrap()
+            })
+            .collect();
+        let file = File::reconstruct(&header, &dec[..]).unwrap();
+        assert_eq!(&original[..], &file.unpack().unwrap()[..]);
+    }
+}
+
+#[test]
+fn segments() {
+    let mut rng = rand::thread_rng();
+    let original: Vec<u8> = (0..10000).map(|_| rng.gen()).collect();
+
+    let params = Params {
+        segment: 3,
+        ..Default::default()
+    };
+    let file = File::with_params(&original[..], &params);
+    assert_eq!(&original[..], &file.unpack().unwrap()[..]);
+
+    // recorded in the header
+    let (header, shards) = file.shards(2).unwrap();
+    let header = Header::deserialize(&header.serialize()[..]).unwrap();
+    assert_eq!(header.segment(), 3);
+
+    // the transform is local to a segment
+    let whole = File::new(&original[..]);
+    assert_ne!(whole.shards[0].unpack(), file.shards[0].unpack());
+    assert_eq!(
+        File::with_params(&original[..4000], &params).shards[1].unpack(),
+        file.shards[1].unpack()
+    );
+
+    for _ in 0..20 {
+        let offset = rng.gen::<usize>() % 10100;
+        let len = rng.gen::<usize>() % 5000;
+        let end = (offset + len).min(original.len());
+        let expected = &original[offset.min(end)..end];
+
+        // only the data shards of the covering segments
+        let first = offset / (3 * SHARD_SIZE) * 3;
+        let last = end.saturating_sub(1) / (3 * SHARD_SIZE) * 3 + 3;
+        let covering: Vec<Shard> = shards
+            .iter()
+            .filter(|s| first <= s.idx as usize && (s.idx as usize) < last)
+            .cloned()
+            .collect();
+        let range = File::reconstruct_range(&header, &covering[..], offset, len).unwrap();
+        assert_eq!(&range[..], expected);
+
+        // missing data shard: uses the parity shards
+        let lossy: Vec<Shard> = shards.iter().skip(1).cloned().collect();
+        let range = File::reconstruct_range(&header, &lossy[..], offset, len).unwrap();
+        assert_eq!(&range[..], expected);
+    }
+
+    // whole file transform
+    let (header, shards) = whole.shards(0).unwrap();
+    let range = File::reconstruct_range(&header, &shards[..], 1000, 3000).unwrap();
+    assert_eq!(&range[..], &original[1000..4000]);
+
+    // hostile header: memory is only allocated for the shards given
+    let (header, shards) = file.shards(2).unwrap();
+    let mut hostile = Header::deserialize(&header.serialize()[..]).unwrap();
+    hostile.length = 1 << 60;
+    hostile.shard_size = 1 << 31;
+    hostile.segment = u16::MAX as usize;
+    for given in [&[][..], &shards[..]] {
+        assert!(matches!(
+            File::reconstruct_range(&hostile, given, 1 << 50, 1 << 40),
+            Err(Error::Reconstruct)
+        ));
+    }
+}
+
+#[test]
+fn read_range() {
+    let mut rng = rand::thread_rng();
+    let original: Vec<u8> = (0..7000).map(|_| rng.gen()).collect();
+
+    for segment in [0, 1, 2] {
+        let params = Params {
+            segment,
+            ..Default::default()
+        };
+        let file = File::with_params(&original[..], &params);
+
+        for _ in 0..20 {
+            let offset = rng.gen::<usize>() % 7100;
+            let len = rng.gen::<usize>() % 3000;
+            let end = (offset + len).min(original.len());
+            let range = file.read_range(offset, len).unwrap();
+            assert_eq!(&range[..], &original[offset.min(end)..end]);
+        }
+
+        // sequential reads
+        let mut rd = file.reader().unwrap();
+        let mut all = Vec::new();
+        rd.read_to_end(&mut all).unwrap();
+        assert_eq!(all, original);
+
+        // seeking
+        let mut buf = [0u8; 1500];
+        assert_eq!(rd.seek(SeekFrom::Start(1000)).unwrap(), 1000);
+        rd.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &original[1000..2500]);
+        assert_eq!(rd.seek(SeekFrom::Current(-500)).unwrap(), 2000);
+        rd.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &original[2000..3500]);
+        assert_eq!(rd.seek(SeekFrom::End(-100)).unwrap(), 6900);
+        assert_eq!(rd.read(&mut buf).unwrap(), 100);
+        assert_eq!(rd.read(&mut buf).unwrap(), 0);
+        assert!(rd.seek(SeekFrom::Current(-8000)).is_err());
+    }
+}
+
+#[test]
+fn encrypted_file() {
+    let mut sk = EncodingKey::new();
+    let mut pk = sk.decoding();
+    let original: Vec<u8> = (0..3000).map(|i| (i % 13) as u8).collect();
+
+    let key = FileKey::generate().unwrap();
+    let params = Params {
+        key: Some(key.clone()),
+        ..Default::default()
+    };
+    let (header, shards) = File::with_params(&original[..], &params).shards(1).unwrap();
+    let header = Header::deserialize(&header.serialize()[..]).unwrap();
+    assert!(header.encrypted());
+
+    let dec: Vec<Shard> = shards
+        .into_iter()
+        .skip(1)
+        .map(|s| {
+            let mut e = s.pack();
+            sk.encode(&mut e);
+            pk.decode(&mut e).unwrap();
+            e.unpack().unwrap()
+        })
+        .collect();
+    let file = File::reconstruct(&header, &dec[..]).unwrap();
+
+    // only the key recovers the contents
+    assert_eq!(file.unpack_with_key(&key).unwrap(), original);
+    assert!(matches!(file.unpack(), Err(Error::Encrypted)));
+    assert!(matches!(file.read_range(0, 10), Err(Error::Encrypted)));
+    assert!(matches!(file.reader(), Err(Error::Encrypted)));
+    assert!(matches!(
+        File::reconstruct_range(&header, &dec[..], 0, 10),
+        Err(Error::Encrypted)
+    ));
+
+    // wrong key
+    let other = FileKey::from_bytes([7; FileKey::SIZE]);
+    assert!(matches!(file.unpack_with_key(&other), Err(Error::Decrypt)));
+
+    // tampered header
+    let mut ser = header.serialize();
+    ser[1 + 8 + 1 + 4 + 2 + 1] ^= 1;
+    let header = Header::deserialize(&ser[..]).unwrap();
+    let file = File::reconstruct(&header, &dec[..]).unwrap();
+    assert!(matches!(file.unpack_with_key(&key), Err(Error::Decrypt)));
+}
+
+#[test]
+fn compression() {
+    let original: Vec<u8> = (0..20000).map(|i| (i / 100) as u8).collect();
+    let key = FileKey::generate().unwrap();
+
+    for encrypt in [false, true] {
+        let params = Params {
+            compression: Compression::Deflate,
+            key: if encrypt { Some(key.clone()) } else { None },
+            ..Default::default()
+        };
+        let file = File::with_params(&original[..], &params);
+        let (header, shards) = file.shards(1).unwrap();
+        let header = Header::deserialize(&header.serialize()[..]).unwrap();
+        assert_eq!(header.compression(), Compression::Deflate);
+        assert_eq!(header.original_length(), original.len());
+
+        // fewer shards than without compression
+        assert!(header.shards() < File::new(&original[..]).shards.len());
+
+        let file = File::reconstruct(&header, &shards[1..]).unwrap();
+        assert_eq!(file.unpack_with_key(&key).unwrap(), original);
+        if !encrypt {
+            assert_eq!(file.unpack().unwrap(), original);
+        }
+    }
+}
+
+#[test]
+fn compressed_ranges() {
+    let original: Vec<u8> = (0..20000).map(|i| (i / 100) as u8).collect();
+
+    for segment in [0, 1] {
+        let params = Params {
+            segment,
+            compression: Compression::Deflate,
+            ..Default::default()
+        };
+        let (header, shards) = File::with_params(&original[..], &params).shards(1).unwrap();
+        let file = File::reconstruct(&header, &shards[..]).unwrap();
+
+        // ranges of the contents rather than of the compressed bytes
+        assert_eq!(
+            file.read_range(15000, 2000).unwrap(),
+            &original[15000..17000]
+        );
+        assert_eq!(file.read_range(19990, 100).unwrap(), &original[19990..]);
+        assert_eq!(
+            File::reconstruct_range(&header, &shards[..], 1000, 3000).unwrap(),
+            &original[1000..4000]
+        );
+
+        let mut rd = file.reader().unwrap();
+        let mut all = Vec::new();
+        rd.read_to_end(&mut all).unwrap();
+        assert_eq!(all, original);
+        assert_eq!(rd.seek(SeekFrom::End(-100)).unwrap(), 19900);
+        let mut buf = [0u8; 200];
+        assert_eq!(rd.read(&mut buf).unwrap(), 100);
+        assert_eq!(&buf[..100], &original[19900..]);
+
+        // a wrong (or hostile) original length is reported rather than ignored
+        for bad in [original.len() - 1, original.len() + 1, usize::MAX] {
+            let mut header = Header::deserialize(&header.serialize()[..]).unwrap();
+            header.original = bad;
+            let file = File::reconstruct(&header, &shards[..]).unwrap();
+            assert!(matches!(file.unpack(), Err(Error::Malformed)));
+            assert!(matches!(file.read_range(0, 10), Err(Error::Malformed)));
+            assert!(matches!(file.reader(), Err(Error::Malformed)));
+        }
+    }
+}
+
+#[test]
+fn digest() {
+    let original: Vec<u8> = (0..5000).map(|i| (i % 7) as u8).collect();
+
+    for compression in [Compression::None, Compression::Deflate] {
+        let params = Params {
+            compression,
+            ..Default::default()
+        };
+        let (header, shards) = File::with_params(&original[..], &params).shards(1).unwrap();
+        let header = Header::deserialize(&header.serialize()[..]).unwrap();
+        assert_eq!(header.digest(), Some(&primitives::sha256(&original[..])));
+
+        let file = File::reconstruct(&header, &shards[..]).unwrap();
+        assert_eq!(file.unpack_verified().unwrap(), original);
+
+        // a corrupted shard is detected
+        let mut bad = shards.clone();
+        bad[0].coords[3][0] ^= 1;
+        let file = File::reconstruct(&header, &bad[..]).unwrap();
+        assert!(matches!(file.unpack_verified(), Err(Error::Digest)));
+    }
+
+    // not recorded for encrypted files
+    let params = Params {
+        key: Some(FileKey::generate().unwrap()),
+        ..Default::default()
+    };
+    let file = File::with_params(&original[..], &params);
+    let (header, _) = file.shards(0).unwrap();
+    assert!(header.digest().is_none());
+    assert!(matches!(file.unpack_verified(), Err(Error::Encrypted)));
+}
+
+#[test]
+fn truncated_header() {
+    // every optional field present
+    let params = Params {
+        key: Some(FileKey::generate().unwrap()),
+        compression: Compression::Deflate,
+        ..Default::default()
+    };
+    let original: Vec<u8> = (0..3000).map(|i| (i % 7) as u8).collect();
+    let (mut header, _) = File::with_params(&original[..], &params).shards(1).unwrap();
+    header.set_fingerprint(Fingerprint::from_bytes([1; Fingerprint::SIZE]));
+    header.set_shard_fingerprint(2, Fingerprint::from_bytes([2; Fingerprint::SIZE]));
+    header.digest = Some([3; 32]);
+
+    let ser = header.serialize();
+    assert!(Header::deserialize(&ser[..]).is_ok());
+    for len in 0..ser.len() {
+        assert!(matches!(
+            Header::deserialize(&ser[..len]),
+            Err(Error::Malformed)
+        ));
+    }
+}
+
+#[test]
+fn shard_size() {
+    let mut sk = EncodingKey::new();
+    let mut pk = sk.decoding();
+
+    let mut rng = rand::thread_rng();
+    let original: Vec<u8> = (0..20000).map(|_| rng.gen()).collect();
+
+    let params = Params {
+        shard_size: 8 * BLOCK_SIZE,
+        segment: 2,
+        ..Default::default()
+    };
+    let (header, shards) = File::with_params(&original[..], &params).shards(2).unwrap();
+    let header = Header::deserialize(&header.serialize()[..]).unwrap();
+    assert_eq!(header.shard_size(), 4096);
+    assert_eq!(header.shards(), 5);
+
+    // encode / decode the larger shards
+    let mut enc: Vec<EncodedShard> = shards.iter().map(|s| s.pack()).collect();
+    sk.encode_batch(&mut enc[..]);
+    let enc: Vec<EncodedShard> = enc
+        .iter()
+        .map(|s| EncodedShard::deserialize(&s.serialize()[..]).unwrap())
+        .collect();
+    let decoded: Vec<Shard> = enc
+        .into_iter()
+        .skip(2)
+        .map(|mut s| {
+            pk.decode(&mut s).unwrap();
+            s.unpack().unwrap()
+        })
+        .collect();
+
+    let file = File::reconstruct(&header, &decoded[..]).unwrap();
+    assert_eq!(file.unpack_verified().unwrap(), original);
+    let range = File::reconstruct_range(&header, &decoded[..], 9000, 5000).unwrap();
+    assert_eq!(&range[..], &original[9000..14000]);
+
+    // shards of a different size are rejected
+    let (_, small) = File::new(&original[..]).shards(0).unwrap();
+    assert!(matches!(
+        File::reconstruct(&header, &small[..]),
+        Err(Error::Reconstruct)
+    ));
+}
+
+#[test]
+fn pack_unpack() {
+    let mut rng = rand::thread_rng();
+    for blocks in [1, 2, 5] {
+        let bytes: Vec<u8> = (0..blocks * BLOCK_SIZE).map(|_| rng.gen()).collect();
+        let shard = Shard::new(4, PrfKind::default(), &bytes[..]);
+        let enc = shard.pack_replica(1);
+        assert_eq!(enc.blocks.len(), blocks);
+
+        // (unencoded) blocks survive cloning and serialization
+        let ser = enc.clone().serialize();
+        let dec = EncodedShard::deserialize(&ser[..]).unwrap();
+        assert_eq!(dec.unpack().unwrap().unpack(), bytes);
+    }
+}
+
+#[test]
+fn forged_shard() {
+    let shard = Shard::new(2, PrfKind::default(), &[3; SHARD_SIZE]);
+    let mut ser = shard.pack().serialize();
+
+    // block values larger than the block half size
+    let n = ser.len();
+    ser[n - MODULUS_BYTES..].fill(0xff);
+    let forged = EncodedShard::deserialize(&ser[..]).unwrap();
+    assert!(matches!(forged.unpack(), Err(Error::OutOfRange)));
+}
+
+#[test]
+fn encode_file() {
+    let mut sk = EncodingKey::new();
+    let mut pk = sk.decoding();
+    let mut other = EncodingKey::new().decoding();
+
+    let original: Vec<u8> = (0..3000).map(|i| (i * 7) as u8).collect();
+    let file = File::new(&original[..]);
+    let (header, enc) = sk.encode_file(&file, 2).unwrap();
+    assert_eq!(header.fingerprint(), Some(&sk.fingerprint()));
+    assert_eq!(enc.len(), header.shards() + 2);
+
+    // same as encoding shard by shard
+    let (_, shards) = file.shards(2).unwrap();
+    for (s, e) in shards.iter().zip(enc.iter()) {
+        assert_eq!(sk.encode_shard(s).serialize(), e.serialize());
+        assert_eq!(pk.decode_shard(e).unwrap().unpack(), s.unpack());
+    }
+
+    // any sufficiently large subset
+    let file = pk.decode_file(&header, &enc[2..]).unwrap();
+    assert_eq!(file.unpack_verified().unwrap(), original);
+
+    assert!(matches!(
+        other.decode_file(&header, &enc[..]),
+        Err(Error::KeyMismatch)
+    ));
+    assert!(matches!(
+        pk.decode_file(&header, &enc[3..]),
+        Err(Error::Reconstruct)
+    ));
+}
+
+#[test]
+fn accessors() {
+    let mut sk = EncodingKey::new();
+    let mut pk = sk.decoding();
+
+    // none of the parameters are the defaults
+    let mut rng = rand::thread_rng();
+    let mut original: Vec<u8> = (0..3000).map(|_| rng.gen()).collect();
+    original.extend((0..20000).map(|i| (i % 251) as u8));
+    let params = Params {
+        prf: PrfKind::Shake256,
+        shard_size: 2 * BLOCK_SIZE,
+        segment: 2,
+        compression: Compression::Deflate,
+        ..Default::default()
+    };
+    let file = File::with_params(&original[..], &params);
+    let (header, shards) = file.shards(1).unwrap();
+    assert_eq!(header.original_length(), original.len());
+    assert!(header.stored_length() < original.len());
+    assert_eq!(header.length(), header.stored_length());
+    assert_eq!(
+        header.shards(),
+        header.stored_length().div_ceil(2 * BLOCK_SIZE)
+    );
+    assert_eq!(header.shard_size(), 2 * BLOCK_SIZE);
+    assert_eq!(header.segment(), 2);
+    assert_eq!(header.compression(), Compression::Deflate);
+    assert!(!header.encrypted());
+    assert_eq!(shards[1].index(), 1);
+    assert_eq!(shards[1].prf(), PrfKind::Shake256);
+    assert_eq!(shards[1].as_bytes().len(), 2 * BLOCK_SIZE);
+
+    // rebuild the shards from their contents
+    let rebuilt: Vec<Shard> = shards
+        .iter()
+        .map(|s| {
+            let mut r = Shard::from_bytes(s.index(), s.as_bytes()).unwrap();
+            r.set_prf(header.prf);
+            r
+        })
+        .collect();
+    let e = sk.encode_shard(&rebuilt[2]);
+    assert_eq!(e.serialize(), sk.encode_shard(&shards[2]).serialize());
+    assert_eq!(
+        (e.index(), e.prf(), e.size()),
+        (2, PrfKind::Shake256, 2 * BLOCK_SIZE)
+    );
+    assert_eq!(
+        pk.decode_shard(&e).unwrap().as_bytes(),
+        shards[2].as_bytes()
+    );
+
+    let file = File::reconstruct(&header, &rebuilt[1..]).unwrap();
+    assert_eq!(file.unpack_verified().unwrap(), original);
+
+    // invalid sizes
+    assert!(matches!(Shard::from_bytes(0, &[]), Err(Error::Malformed)));
+    assert!(matches!(
+        Shard::from_bytes(0, &[0; 1000]),
+        Err(Error::Malformed)
+    ));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde() {
+    let mut sk = EncodingKey::new();
+    let pk = sk.decoding();
+
+    let original: Vec<u8> = (0..1500).map(|i| (i % 13) as u8).collect();
+    let (header, enc) = sk.encode_file(&File::new(&original[..]), 1).unwrap();
+    let (_, shards) = File::new(&original[..]).shards(1).unwrap();
+
+    // hex strings in JSON
+    let json = serde_json::to_string(&header).unwrap();
+    assert_eq!(from_hex(json.trim_matches('"')), header.serialize());
+    let header: Header = serde_json::from_str(&json).unwrap();
+
+    let json = serde_json::to_string(&enc).unwrap();
+    let enc: Vec<EncodedShard> = serde_json::from_str(&json).unwrap();
+
+    let json = serde_json::to_string(&shards[1]).unwrap();
+    let shard: Shard = serde_json::from_str(&json).unwrap();
+    assert_eq!((shard.index(), shard.as_bytes()), (1, shards[1].as_bytes()));
+
+    let json = serde_json::to_string(&pk).unwrap();
+    let mut pk: DecodingKey = serde_json::from_str(&json).unwrap();
+    let file = pk.decode_file(&header, &enc[..]).unwrap();
+    assert_eq!(file.unpack_verified().unwrap(), original);
+
+    // invalid hex: bad digit, odd length, non-ASCII
+    for bad in ["\"0g\"", "\"012\"", "\"0é\"", "17", "[1, 2]"] {
+        assert!(serde_json::from_str::<Header>(bad).is_err());
+    }
+    assert!(serde_json::from_str::<Shard>("\"000100\"").is_err());
+
+    // truncated serializations
+    let json = serde_json::to_string(&header).unwrap();
+    assert!(serde_json::from_str::<Header>(&format!("{}\"", &json[..json.len() - 3])).is_err());
+    let json = serde_json::to_string(&enc[0]).unwrap();
+    assert!(
+        serde_json::from_str::<EncodedShard>(&format!("{}\"", &json[..json.len() - 3])).is_err()
+    );
+}
+
+#[test]
+fn archive() {
+    let mut sk = EncodingKey::new();
+    let mut pk = sk.decoding();
+
+    let original: Vec<u8> = (0..3000).map(|i| (i % 17) as u8).collect();
+    let (header, enc) = sk.encode_file(&File::new(&original[..]), 2).unwrap();
+
+    let mut w = ArchiveWriter::new(std::io::Cursor::new(Vec::new()), &header).unwrap();
+    for s in enc.iter() {
+        w.write_shard(s).unwrap();
+    }
+    let mut bytes = w.finish().unwrap().into_inner();
+
+    let mut ar = ArchiveReader::open(std::io::Cursor::new(&bytes[..])).unwrap();
+    assert_eq!(ar.indices(), vec![0, 1, 2, 3, 4]);
+    assert_eq!(ar.fingerprint(), Some(&sk.fingerprint()));
+    assert_eq!(ar.header().serialize(), header.serialize());
+    assert_eq!(ar.load(3).unwrap().unwrap().serialize(), enc[3].serialize());
+    assert!(ar.load(5).unwrap().is_none());
+
+    // a corrupted shard is skipped
+    let n = bytes.len();
+    bytes[n - 1] ^= 1;
+    let mut ar = ArchiveReader::open(std::io::Cursor::new(&bytes[..])).unwrap();
+    assert!(matches!(ar.load(4), Err(Error::Checksum)));
+    let shards = ar.shards().unwrap();
+    assert_eq!(shards.len(), 4);
+    let file = pk.decode_file(ar.header(), &shards[..]).unwrap();
+    assert_eq!(file.unpack_verified().unwrap(), original);
+
+    // truncated
+    assert!(matches!(
+        ArchiveReader::open(std::io::Cursor::new(&bytes[..n - 1])),
+        Err(Error::Malformed)
+    ));
+    let start = 8 + 1 + 4 + header.serialize().len() + 1 + Fingerprint::SIZE;
+    for len in (0..start).chain([start + 1, start + 40]) {
+        assert!(matches!(
+            ArchiveReader::open(std::io::Cursor::new(&bytes[..len])),
+            Err(Error::Malformed)
+        ));
+    }
+
+    // hostile header length
+    let mut hostile = bytes.clone();
+    hostile[9..13].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(matches!(
+        ArchiveReader::open(std::io::Cursor::new(&hostile[..])),
+        Err(Error::Malformed)
+    ));
+
+    // appending the corrupted shard again replaces it
+    let mut w = ArchiveWriter::new(std::io::Cursor::new(Vec::new()), &header).unwrap();
+    w.write_shard(&enc[4]).unwrap();
+    bytes.extend(&w.finish().unwrap().into_inner()[start..]);
+    let mut ar = ArchiveReader::open(std::io::Cursor::new(&bytes[..])).unwrap();
+    assert_eq!(ar.indices(), vec![0, 1, 2, 3, 4]);
+    assert_eq!(ar.load(4).unwrap().unwrap().serialize(), enc[4].serialize());
+    assert_eq!(ar.shards().unwrap().len(), 5);
+}
+
+#[test]
+fn too_many_shards() {
+    let file = File::new(&[1; 10]);
+    for expansion in [70000, MAX_SHARDS, usize::MAX] {
+        assert!(matches!(file.shards(expansion), Err(Error::TooManyShards)));
+    }
+    assert_eq!(file.shards(MAX_SHARDS - 1).unwrap().1.len(), MAX_SHARDS);
+}
+
+#[test]
+fn shard_ids() {
+    let mut sk = EncodingKey::new();
+
+    let original: Vec<u8> = (0..2500).map(|i| (i % 11) as u8).collect();
+    let (_, shards, mut manifest) = File::new(&original[..]).shards_with_ids(1).unwrap();
+    assert_eq!(manifest.entries().len(), 4);
+
+    // deterministic splits share the plain hashes
+    let (_, again) = File::new(&original[..]).shards(1).unwrap();
+    assert_eq!(shards[2].id(), again[2].id());
+    assert_ne!(shards[1].id(), shards[2].id());
+    assert_eq!(manifest.find(&shards[2].id()).unwrap().index, 2);
+
+    // every replica has a distinct encoded hash
+    let e0 = sk.encode_shard(&shards[2]);
+    let mut e1 = shards[2].pack_replica(1);
+    sk.encode(&mut e1);
+    assert_ne!(e0.id(), e1.id());
+    manifest.record(&e0).unwrap();
+    manifest.record(&e1).unwrap();
+    manifest.record(&e1).unwrap();
+    assert_eq!(manifest.entries().len(), 5);
+
+    let entry = manifest.find(&e1.id()).unwrap();
+    assert_eq!((entry.index, entry.replica), (2, 1));
+    assert_eq!(entry.plain, shards[2].id());
+
+    // the attached key fingerprint is not part of the identifier
+    let mut e2 = e1.clone();
+    e2.set_fingerprint(None);
+    assert_eq!(e2.id(), e1.id());
+    e2.set_fingerprint(Some(Fingerprint::from_bytes([7; Fingerprint::SIZE])));
+    assert_eq!(e2.id(), e1.id());
+    assert_ne!(e2.serialize(), e1.serialize());
+
+    // a shard of another file
+    let (_, other) = File::new(&[1; 9000]).shards(1).unwrap();
+    assert!(matches!(
+        manifest.record(&sk.encode_shard(&other[9])),
+        Err(Error::NotFound)
+    ));
+
+    let ser = manifest.serialize();
+    assert_eq!(ShardManifest::deserialize(&ser[..]).unwrap(), manifest);
+    for len in 0..ser.len() {
+        assert!(matches!(
+            ShardManifest::deserialize(&ser[..len]),
+            Err(Error::Malformed)
+        ));
+    }
+
+    // hostile entry count
+    let mut hostile = ser.clone();
+    hostile[1..5].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(matches!(
+        ShardManifest::deserialize(&hostile[..]),
+        Err(Error::Malformed)
+    ));
+}
+
+#[test]
+fn bundle() {
+    let mut sk = EncodingKey::new();
+    let mut pk = sk.decoding();
+
+    let files: Vec<(String, Vec<u8>)> = (0..20)
+        .map(|i| (format!("file-{}.txt", i), vec![i as u8; 100 * i]))
+        .collect();
+    let mut bundle = Bundle::new();
+    for (name, data) in files.iter() {
+        bundle.add(name, &data[..]).unwrap();
+    }
+    assert!(matches!(
+        bundle.add("file-3.txt", &[]),
+        Err(Error::Malformed)
+    ));
+
+    for (segment, compression) in [
+        (0, Compression::None),
+        (2, Compression::None),
+        (2, Compression::Deflate),
+    ] {
+        let params = Params {
+            segment,
+            compression,
+            ..Default::default()
+        };
+        let (header, enc) = sk.encode_file(&bundle.file(&params), 1).unwrap();
+        let file = pk.decode_file(&header, &enc[..]).unwrap();
+
+        let entries = file.bundle_entries().unwrap();
+        assert_eq!(&entries[..], bundle.entries());
+        for (name, data) in files.iter() {
+            assert_eq!(&file.bundle_extract(name).unwrap(), data);
+        }
+        assert!(matches!(
+            file.bundle_extract("missing"),
+            Err(Error::NotFound)
+        ));
+    }
+
+    // encrypted bundles require the key
+    let key = FileKey::generate().unwrap();
+    let params = Params {
+        key: Some(key.clone()),
+        ..Default::default()
+    };
+    let file = bundle.file(&params);
+    assert!(matches!(file.bundle_entries(), Err(Error::Encrypted)));
+    assert!(matches!(
+        file.bundle_extract("file-1.txt"),
+        Err(Error::Encrypted)
+    ));
+    assert_eq!(
+        &file.bundle_entries_with_key(&key).unwrap()[..],
+        bundle.entries()
+    );
+    assert_eq!(
+        file.bundle_extract_with_key("file-3.txt", &key).unwrap(),
+        files[3].1
+    );
+    let other = FileKey::from_bytes([7; FileKey::SIZE]);
+    assert!(matches!(
+        file.bundle_extract_with_key("file-3.txt", &other),
+        Err(Error::Decrypt)
+    ));
+
+    // index length beyond the contents
+    let mut bytes = bundle.serialize();
+    bytes[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+    for compression in [Compression::None, Compression::Deflate] {
+        let params = Params {
+            compression,
+            ..Default::default()
+        };
+        let file = File::with_params(&bytes[..], &params);
+        assert!(matches!(file.bundle_entries(), Err(Error::Malformed)));
+        assert!(matches!(
+            file.bundle_extract("file-1.txt"),
+            Err(Error::Malformed)
+        ));
+    }
+
+    // not a bundle
+    let file = File::new(&[0; 100]);
+    assert!(matches!(file.bundle_entries(), Err(Error::Malformed)));
+}
+
+#[test]
+fn append() {
+    let mut sk = EncodingKey::new();
+    let mut pk = sk.decoding();
+    let params = Params::default();
+
+    let mut root = RootManifest::new();
+    let mut stored = vec![];
+    let mut original = vec![];
+    for i in 0..3 {
+        let data: Vec<u8> = (0..1000 + 300 * i).map(|j| (i + j) as u8).collect();
+        let (idx, shards) = sk.append(&mut root, &data[..], &params, 1).unwrap();
+        assert_eq!(idx, i);
+        stored.push(shards);
+        original.extend(data);
+    }
+    assert_eq!(root.length(), original.len());
+    assert_eq!(root.locate(900, 300), vec![(0, 900, 100), (1, 0, 200)]);
+    assert_eq!(root.locate(3850, 100), vec![(2, 1550, 50)]);
+    assert_eq!(root.locate(3900, 100), vec![]);
+
+    let root = RootManifest::deserialize(&root.serialize()[..]).unwrap();
+    let decode = |pk: &mut DecodingKey, root: &RootManifest, stored: &[Vec<EncodedShard>]| {
+        let files: Vec<File> = root
+            .stripes()
+            .iter()
+            .zip(stored.iter())
+            .map(|(h, s)| pk.decode_file(h, &s[1..]).unwrap())
+            .collect();
+        root.unpack(&files[..], None)
+    };
+    assert_eq!(decode(&mut pk, &root, &stored[..]).unwrap(), original);
+
+    // update a single stripe
+    let mut root = root;
+    let file = File::new(b"replaced");
+    let (header, shards) = sk.encode_file(&file, 1).unwrap();
+    root.replace(1, header).unwrap();
+    assert!(matches!(
+        decode(&mut pk, &root, &stored[..]),
+        Err(Error::Digest)
+    ));
+    stored[1] = shards;
+    let data = decode(&mut pk, &root, &stored[..]).unwrap();
+    assert_eq!(&data[1000..1008], b"replaced");
+    assert_eq!(data.len(), 1000 + 8 + 1600);
+
+    // sealed stripes next to a plain one
+    let key = FileKey::generate().unwrap();
+    let sealed = Params {
+        key: Some(key.clone()),
+        ..Default::default()
+    };
+    let mut root = RootManifest::new();
+    let mut files = vec![];
+    for (i, params) in [&sealed, &params, &sealed].iter().enumerate() {
+        let (_, shards) = sk.append(&mut root, &[i as u8; 1000], params, 1).unwrap();
+        files.push(pk.decode_file(&root.stripes()[i], &shards[..]).unwrap());
+    }
+    let data = root.unpack(&files[..], Some(&key)).unwrap();
+    assert_eq!(&data[..1000], &[0; 1000][..]);
+    assert_eq!(&data[1000..2000], &[1; 1000][..]);
+    assert_eq!(&data[2000..], &[2; 1000][..]);
+    assert!(matches!(
+        root.unpack(&files[..], None),
+        Err(Error::Encrypted)
+    ));
+    let other = FileKey::from_bytes([7; FileKey::SIZE]);
+    assert!(matches!(
+        root.unpack(&files[..], Some(&other)),
+        Err(Error::Decrypt)
+    ));
+    assert!(matches!(
+        root.unpack(&files[1..], Some(&key)),
+        Err(Error::Reconstruct)
+    ));
+
+    // stripes out of order
+    files.swap(0, 2);
+    assert!(matches!(
+        root.unpack(&files[..], Some(&key)),
+        Err(Error::Digest)
+    ));
+    files.swap(0, 2);
+
+    // stripe of another object (of the same length)
+    files[1] = File::new(&[3; 1000]);
+    assert!(matches!(
+        root.unpack(&files[..], Some(&key)),
+        Err(Error::Digest)
+    ));
+}